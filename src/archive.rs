@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use crate::compress;
+use crate::escape::{display_name, name_bytes, path_from_bytes};
+use crate::hasher::{DataHasher, FileHash, HashError};
+use crate::merkle::MerkleTree;
+use crate::region::Region;
+
+// A member of an archive is named <archive>!<path in archive>. Tar archives can be compressed
+// with gzip, zstd or xz. Only regular files are hashed.
+pub const MEMBER_SEPARATOR: u8 = b'!';
+const ZIP_MAGIC: &[u8] = b"PK";
+// Old tar archives without the ustar magic are not recognized
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+pub fn member_name(archive: &Path, member: &[u8]) -> PathBuf {
+    let mut name = name_bytes(archive.as_os_str());
+    name.push(MEMBER_SEPARATOR);
+    name.extend_from_slice(member);
+
+    return path_from_bytes(name);
+}
+
+// Splits the name of an archive member into the name of the archive and the path in
+// the archive. The archive is the shortest prefix which is a zip or tar archive.
+pub fn split_member_name(name: &Path) -> Option<(PathBuf, Vec<u8>)> {
+    let bytes = name_bytes(name.as_os_str());
+
+    return bytes.iter()
+        .enumerate()
+        .filter(|(_, b)| **b == MEMBER_SEPARATOR)
+        .map(|(i, _)| (path_from_bytes(bytes[..i].to_vec()), bytes[i + 1..].to_vec()))
+        .find(|(archive, _)| is_archive(archive));
+}
+
+fn is_zip(archive: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; 2];
+    let bytes_read = File::open(archive)?.read(&mut magic)?;
+
+    return Ok(magic[..bytes_read] == *ZIP_MAGIC);
+}
+
+fn is_tar(archive: &Path) -> io::Result<bool> {
+    let data = compress::decompress(io::BufReader::new(File::open(archive)?))?;
+    let mut header: Vec<u8> = Vec::new();
+    data.take((TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64).read_to_end(&mut header)?;
+
+    return Ok(header.get(TAR_MAGIC_OFFSET..) == Some(TAR_MAGIC));
+}
+
+fn is_archive(file: &Path) -> bool {
+    return file.is_file() && (is_zip(file).unwrap_or(false) || is_tar(file).unwrap_or(false));
+}
+
+type MemberFn<'a> = dyn FnMut(Vec<u8>, &mut dyn Read) -> Result<(), HashError> + 'a;
+
+// Calls f with the path and the data of each regular file in the archive
+fn for_each_member(archive: &Path, f: &mut MemberFn) -> Result<(), HashError> {
+    let archive_name = display_name(archive);
+    let open_err = |e: io::Error| HashError::FileOpenError(archive_name.clone(), e);
+    let read_err = |e: io::Error| HashError::ReadError(Some(archive_name.clone()), 0, e);
+
+    if is_zip(archive).map_err(open_err)? {
+        let mut zip_archive = zip::ZipArchive::new(File::open(archive).map_err(open_err)?).map_err(|e| read_err(io::Error::other(e)))?;
+
+        for i in 0..zip_archive.len() {
+            let mut member = zip_archive.by_index(i).map_err(|e| read_err(io::Error::other(e)))?;
+
+            if member.is_file() {
+                f(member.name_raw().to_vec(), &mut member)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    let data = compress::decompress(io::BufReader::new(File::open(archive).map_err(open_err)?)).map_err(read_err)?;
+    let mut tar_archive = tar::Archive::new(data);
+
+    for entry in tar_archive.entries().map_err(read_err)? {
+        let mut entry = entry.map_err(read_err)?;
+
+        if entry.header().entry_type().is_file() {
+            let path = entry.path_bytes().to_vec();
+            f(path, &mut entry)?;
+        }
+    }
+
+    return Ok(());
+}
+
+// Hashes the members of archives and delegates all other files to the wrapped FileHash.
+// The hashes of all members of the last used archive are kept, so that each archive only
+// has to be read once as long as its members are processed one after another.
+pub struct ArchiveHasher {
+    inner: Box<dyn FileHash>,
+    archive: Option<PathBuf>,
+    member_hashes: HashMap<Vec<u8>, String>
+}
+
+impl ArchiveHasher {
+    pub fn new(inner: Box<dyn FileHash>) -> ArchiveHasher {
+        return ArchiveHasher {
+            inner,
+            archive: None,
+            member_hashes: HashMap::new()
+        }
+    }
+
+    // Hashes all members of the archive and returns their paths in the order of the archive.
+    // A path which occurs several times is returned once with the hash of its last copy, as
+    // this is the one which is left when the archive is extracted.
+    fn load_archive(&mut self, archive: &Path) -> Result<Vec<Vec<u8>>, HashError> {
+        let mut hashes: HashMap<Vec<u8>, String> = HashMap::new();
+        let mut members: Vec<Vec<u8>> = Vec::new();
+        let inner = self.inner.as_mut();
+
+        for_each_member(archive, &mut |path, data| {
+            let hash = inner.hash_data(data).map_err(|e| e.in_file(&member_name(archive, &path), 0))?;
+
+            if hashes.insert(path.clone(), hash).is_none() {
+                members.push(path);
+            }
+
+            return Ok(());
+        })?;
+
+        self.archive = Some(archive.to_path_buf());
+        self.member_hashes = hashes;
+
+        return Ok(members);
+    }
+
+    fn hash_member(&mut self, file_name: &Path, archive: &Path, member: &[u8]) -> Result<String, HashError> {
+        if self.archive.as_deref() != Some(archive) {
+            self.load_archive(archive)?;
+        }
+
+        return match self.member_hashes.get(member) {
+            Some(hash) => Ok(hash.clone()),
+            None => Err(HashError::FileOpenError(display_name(file_name), io::Error::new(io::ErrorKind::NotFound, "not found in archive")))
+        };
+    }
+}
+
+impl DataHasher for ArchiveHasher {
+    fn hash_data(&mut self, r: &mut dyn Read) -> Result<String, HashError> {
+        return self.inner.hash_data(r);
+    }
+
+    fn hash_data_chunked(&mut self, r: &mut dyn Read, chunk_size: u64) -> Result<(String, Vec<String>), HashError> {
+        return self.inner.hash_data_chunked(r, chunk_size);
+    }
+
+    fn hash_data_tree(&mut self, r: &mut dyn Read, leaf_size: u64) -> Result<MerkleTree, HashError> {
+        return self.inner.hash_data_tree(r, leaf_size);
+    }
+}
+
+impl FileHash for ArchiveHasher {
+    fn get_algo(&self) -> String {
+        return self.inner.get_algo();
+    }
+
+    fn verify_data(&mut self, r: &mut dyn Read, hash: &str) -> HashError {
+        return self.inner.verify_data(r, hash);
+    }
+
+    fn hash_file_chunked(&mut self, file_name: &Path, chunk_size: u64) -> Result<(String, Vec<String>), HashError> {
+        if split_member_name(file_name).is_some() {
+            return Err(HashError::UnsupportedFileType(display_name(file_name)));
+        }
+
+        return self.inner.hash_file_chunked(file_name, chunk_size);
+    }
+
+    fn hash_file_region(&mut self, file_name: &Path, region: &Region) -> Result<String, HashError> {
+        let (archive, member) = match split_member_name(file_name) {
+            Some(m) => m,
+            None => return self.inner.hash_file_region(file_name, region)
+        };
+
+        if !region.is_whole() {
+            return Err(HashError::UnsupportedFileType(display_name(file_name)));
+        }
+
+        return self.hash_member(file_name, &archive, &member);
+    }
+
+    fn expand_name(&mut self, file_name: &Path) -> Result<Vec<PathBuf>, HashError> {
+        let members = self.load_archive(file_name)?;
+
+        return Ok(members.iter().map(|m| member_name(file_name, m)).collect());
+    }
+}
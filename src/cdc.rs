@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::io::Read;
+use crate::hasher::{DataHasher, HashError};
+
+// Content defined chunking as described in "FastCDC: a Fast and Efficient Content-Defined
+// Chunking Approach for Data Deduplication" (Xia et al., 2016) with normalization level 2.
+// Chunk boundaries only depend on the data, the chunk sizes and the gear table, which is
+// generated from a fixed seed. Boundaries found with different sizes can not be compared.
+pub const DEFAULT_AVG_SIZE: u64 = 8192;
+const GEAR_SEED: u64 = 0x727332353673756d;
+
+pub struct ChunkInfo {
+    pub offset: u64,
+    pub length: u64,
+    pub hash: String
+}
+
+// splitmix64
+fn make_gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state = GEAR_SEED;
+
+    for entry in table.iter_mut() {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        *entry = z ^ (z >> 31);
+    }
+
+    return table;
+}
+
+fn make_mask(bits: u32) -> u64 {
+    return !0u64 << (64 - bits);
+}
+
+pub struct Chunker {
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+    mask_s: u64,
+    mask_l: u64,
+    gear: [u64; 256]
+}
+
+impl Chunker {
+    // avg_size has to be a power of two of at least 256. The minimum chunk size is a
+    // quarter and the maximum chunk size eight times the average size.
+    pub fn new(avg_size: u64) -> Option<Chunker> {
+        if !avg_size.is_power_of_two() || !(256..=(1 << 30)).contains(&avg_size) {
+            return None;
+        }
+
+        let bits = avg_size.trailing_zeros();
+
+        return Some(Chunker {
+            min_size: (avg_size / 4) as usize,
+            avg_size: avg_size as usize,
+            max_size: (avg_size * 8) as usize,
+            mask_s: make_mask(bits + 2),
+            mask_l: make_mask(bits - 2),
+            gear: make_gear_table()
+        });
+    }
+
+    // Returns the length of the first chunk in data
+    pub fn cut(&self, data: &[u8]) -> usize {
+        let mut n = data.len();
+
+        if n <= self.min_size {
+            return n;
+        }
+
+        if n > self.max_size {
+            n = self.max_size;
+        }
+
+        let normal_size = std::cmp::min(self.avg_size, n);
+        let mut fp: u64 = 0;
+
+        for (i, b) in data.iter().enumerate().take(n).skip(self.min_size) {
+            fp = (fp << 1).wrapping_add(self.gear[*b as usize]);
+            let mask = if i < normal_size { self.mask_s } else { self.mask_l };
+
+            if (fp & mask) == 0 {
+                return i + 1;
+            }
+        }
+
+        return n;
+    }
+
+    pub fn chunk_data(&self, r: &mut dyn Read, hasher: &mut dyn DataHasher) -> Result<Vec<ChunkInfo>, HashError> {
+        let mut res: Vec<ChunkInfo> = Vec::new();
+        let mut buffer: Vec<u8> = Vec::with_capacity(self.max_size);
+        let mut offset: u64 = 0;
+        let mut eof = false;
+
+        loop {
+            // Keep at least max_size bytes in the buffer as long as there is data
+            while !eof && (buffer.len() < self.max_size) {
+                let old_len = buffer.len();
+                buffer.resize(self.max_size, 0);
+
+                match r.read(&mut buffer[old_len..]) {
+                    Ok(0) => {
+                        buffer.truncate(old_len);
+                        eof = true;
+                    },
+                    Ok(n) => buffer.truncate(old_len + n),
+                    Err(e) => return Err(HashError::ReadError(None, offset + old_len as u64, e))
+                }
+            }
+
+            if buffer.is_empty() {
+                return Ok(res);
+            }
+
+            let chunk_len = self.cut(&buffer);
+            let hash = hasher.hash_data(&mut &buffer[..chunk_len])?;
+            res.push(ChunkInfo { offset, length: chunk_len as u64, hash });
+
+            offset += chunk_len as u64;
+            buffer.drain(..chunk_len);
+        }
+    }
+}
+
+pub struct DedupStats {
+    seen: HashSet<String>,
+    pub total_chunks: u64,
+    pub total_bytes: u64,
+    pub unique_bytes: u64
+}
+
+impl DedupStats {
+    pub fn new() -> DedupStats {
+        return DedupStats {
+            seen: HashSet::new(),
+            total_chunks: 0,
+            total_bytes: 0,
+            unique_bytes: 0
+        }
+    }
+
+    pub fn add(&mut self, chunk: &ChunkInfo) {
+        self.total_chunks += 1;
+        self.total_bytes += chunk.length;
+
+        if self.seen.insert(chunk.hash.clone()) {
+            self.unique_bytes += chunk.length;
+        }
+    }
+
+    pub fn unique_chunks(&self) -> u64 {
+        return self.seen.len() as u64;
+    }
+
+    pub fn duplicate_bytes(&self) -> u64 {
+        return self.total_bytes - self.unique_bytes;
+    }
+
+    pub fn message(&self) -> String {
+        let ratio = if self.unique_bytes == 0 { 1.0 } else { self.total_bytes as f64 / self.unique_bytes as f64 };
+
+        return format!("{} chunks ({} unique), {} bytes: {} unique bytes, {} duplicate bytes, deduplication ratio {:.2}",
+            self.total_chunks, self.unique_chunks(), self.total_bytes, self.unique_bytes, self.duplicate_bytes(), ratio);
+    }
+}
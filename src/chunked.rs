@@ -0,0 +1,195 @@
+use std::io::Read;
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::path::PathBuf;
+use crate::formatter::{HashLineFormatter, ParseError};
+use crate::reffile::{ArmorLines, RefFileError, next_entry_line, skip_region_line};
+use crate::region::{Region, is_region_line};
+
+// Chunk hashes follow the line of the file they belong to:
+// CHUNK <chunk size> <chunk index> <hash>
+pub const CHUNK_PREFIX: &str = "CHUNK ";
+
+// Chunk hashes are only recorded for whole files, so an entry either has chunk hashes or
+// a region
+pub struct ChunkedEntry {
+    pub file_name: PathBuf,
+    pub hash: String,
+    pub chunk_size: u64,
+    pub chunk_hashes: Vec<String>,
+    pub region: Region
+}
+
+pub fn is_chunk_line(line: &str) -> bool {
+    return line.starts_with(CHUNK_PREFIX);
+}
+
+pub fn format_chunks(chunk_size: u64, chunk_hashes: &[String]) -> Vec<String> {
+    return chunk_hashes.iter()
+        .enumerate()
+        .map(|(i, h)| format!("{}{} {} {}", CHUNK_PREFIX, chunk_size, i, h))
+        .collect();
+}
+
+// Returns (chunk size, chunk index, hash)
+pub fn parse_chunk_line(line: &str) -> Result<(u64, usize, String), ParseError> {
+    let format_err = || ParseError::FormatError(String::from(line));
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    if (fields.len() != 4) || (fields[0] != CHUNK_PREFIX.trim()) {
+        return Err(format_err());
+    }
+
+    let chunk_size: u64 = fields[1].parse().map_err(|_| format_err())?;
+    let index: usize = fields[2].parse().map_err(|_| format_err())?;
+
+    if (chunk_size == 0) || !fields[3].chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format_err());
+    }
+
+    return Ok((chunk_size, index, String::from(fields[3])));
+}
+
+// Parses a size given in bytes with an optional K, M or G suffix (powers of 1024)
+pub fn parse_size(size: &str) -> Option<u64> {
+    let (digits, factor) = match size.chars().last()? {
+        'K' | 'k' => (&size[..size.len() - 1], 1u64 << 10),
+        'M' | 'm' => (&size[..size.len() - 1], 1u64 << 20),
+        'G' | 'g' => (&size[..size.len() - 1], 1u64 << 30),
+        _ => (size, 1u64)
+    };
+
+    let res = digits.parse::<u64>().ok()?.checked_mul(factor)?;
+
+    return if res == 0 { None } else { Some(res) };
+}
+
+pub enum RangeProblem {
+    Different(u64, u64),
+    Missing(u64),
+    Additional(u64)
+}
+
+impl RangeProblem {
+    pub fn message(&self) -> String {
+        match self {
+            RangeProblem::Different(start, end) => format!("bytes {}-{} differ", start, end),
+            RangeProblem::Missing(start) => format!("data from byte {} on is missing", start),
+            RangeProblem::Additional(start) => format!("additional data from byte {} on", start)
+        }
+    }
+}
+
+// Compares the chunk hashes of a file of length file_len with the reference hashes and
+// returns the byte ranges which differ. Adjacent differing chunks are merged into one range.
+pub fn find_bad_ranges(chunk_size: u64, file_len: u64, expected: &[String], actual: &[String]) -> Vec<RangeProblem> {
+    let mut res: Vec<RangeProblem> = Vec::new();
+    let mut range_start: Option<u64> = None;
+    let common = std::cmp::min(expected.len(), actual.len());
+
+    for i in 0..common {
+        let chunk_start = (i as u64) * chunk_size;
+
+        if !expected[i].eq_ignore_ascii_case(&actual[i]) {
+            range_start.get_or_insert(chunk_start);
+        } else if let Some(start) = range_start.take() {
+            res.push(RangeProblem::Different(start, chunk_start - 1));
+        }
+    }
+
+    if let Some(start) = range_start {
+        let end = std::cmp::min((common as u64) * chunk_size, file_len);
+        res.push(RangeProblem::Different(start, end - 1));
+    }
+
+    if expected.len() > actual.len() {
+        res.push(RangeProblem::Missing(file_len));
+    } else if actual.len() > expected.len() {
+        res.push(RangeProblem::Additional((common as u64) * chunk_size));
+    }
+
+    return res;
+}
+
+pub struct ChunkedRefFile<R : Read> {
+    parser: Rc<dyn HashLineFormatter>,
+    lines: Peekable<ArmorLines<R>>,
+    failed: bool
+}
+
+impl<R : Read> ChunkedRefFile<R> {
+    pub fn new(s: R, p: &Rc<dyn HashLineFormatter>) -> Self {
+        return ChunkedRefFile {
+            parser: p.clone(),
+            lines: ArmorLines::new(s).peekable(),
+            failed: false
+        }
+    }
+
+    fn next_chunk_line(&mut self) -> Option<(usize, String)> {
+        return self.lines.next_if(|l| matches!(l, Ok((_, l)) if is_chunk_line(l)))?.ok();
+    }
+
+    // Reads the chunk hashes following the line of a file
+    fn read_chunks(&mut self, entry: &mut ChunkedEntry) -> Result<(), RefFileError> {
+        while let Some((line_no, chunk_line)) = self.next_chunk_line() {
+            let (chunk_size, index, chunk_hash) = parse_chunk_line(&chunk_line)
+                .map_err(|e| RefFileError::Malformed(line_no, e))?;
+
+            if ((entry.chunk_size != 0) && (entry.chunk_size != chunk_size)) || (index != entry.chunk_hashes.len()) {
+                return Err(RefFileError::Malformed(line_no, ParseError::FormatError(chunk_line)));
+            }
+
+            entry.chunk_size = chunk_size;
+            entry.chunk_hashes.push(chunk_hash);
+        }
+
+        return Ok(());
+    }
+
+    // Reads the region following the line of a file
+    fn read_region(&mut self, entry: &mut ChunkedEntry) -> Result<(), RefFileError> {
+        let (line_no, region_line) = match self.lines.next_if(|l| matches!(l, Ok((_, l)) if is_region_line(l))) {
+            Some(Ok(l)) => l,
+            _ => return Ok(())
+        };
+
+        if !entry.chunk_hashes.is_empty() {
+            return Err(RefFileError::Malformed(line_no, ParseError::FormatError(region_line)));
+        }
+
+        entry.region = Region::parse(&region_line).map_err(|e| RefFileError::Malformed(line_no, e))?;
+
+        return Ok(());
+    }
+}
+
+impl<R : Read> Iterator for ChunkedRefFile<R> {
+    type Item = Result<ChunkedEntry, RefFileError>;
+
+    fn next(&mut self) -> Option<Result<ChunkedEntry, RefFileError>> {
+        let (line_no, line_raw) = match next_entry_line(&mut self.lines, &mut self.failed)? {
+            Ok(l) => l,
+            Err(e) => return Some(Err(e))
+        };
+
+        let (file_name, hash) = match self.parser.parse(&line_raw) {
+            Ok(i) => i,
+            Err(e) => {
+                while self.next_chunk_line().is_some() {}
+                skip_region_line(&mut self.lines, &line_raw);
+                return Some(Err(RefFileError::Malformed(line_no, e)));
+            }
+        };
+
+        let mut entry = ChunkedEntry { file_name, hash, chunk_size: 0, chunk_hashes: Vec::new(), region: Region::default() };
+
+        if let Err(e) = self.read_chunks(&mut entry).and_then(|_| self.read_region(&mut entry)) {
+            // Skip the remaining chunk hashes of the file
+            while self.next_chunk_line().is_some() {}
+            return Some(Err(e));
+        }
+
+        return Some(Ok(entry));
+    }
+}
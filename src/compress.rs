@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use crate::escape::display_name;
+use crate::hasher::{DataHasher, FileHash, HashError, STDIN_NAME};
+use crate::merkle::MerkleTree;
+use crate::region::{skip_to_region, Region, RegionReader};
+
+// Compressed data is recognized by the magic bytes at its start
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compression {
+    Uncompressed,
+    Gzip,
+    Zstd,
+    Xz
+}
+
+pub fn detect(data: &[u8]) -> Compression {
+    if data.starts_with(GZIP_MAGIC) {
+        return Compression::Gzip;
+    }
+
+    if data.starts_with(ZSTD_MAGIC) {
+        return Compression::Zstd;
+    }
+
+    if data.starts_with(XZ_MAGIC) {
+        return Compression::Xz;
+    }
+
+    return Compression::Uncompressed;
+}
+
+// Returns a reader for the decompressed data of r. Data which is not compressed is
+// returned as it is.
+pub fn decompress<'a, R: BufRead + 'a>(r: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut r = r;
+
+    return match detect(r.fill_buf()?) {
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(r))),
+        Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(r)?)),
+        Compression::Xz => Ok(Box::new(xz2::bufread::XzDecoder::new_multi_decoder(r))),
+        Compression::Uncompressed => Ok(Box::new(r))
+    };
+}
+
+// Hashes the decompressed data of compressed files. Files which are not compressed are
+// hashed as they are. Regions and chunks refer to the decompressed data.
+pub struct DecompressHasher {
+    inner: Box<dyn FileHash>
+}
+
+impl DecompressHasher {
+    pub fn new(inner: Box<dyn FileHash>) -> DecompressHasher {
+        return DecompressHasher { inner };
+    }
+
+    fn open(file_name: &Path) -> Result<Box<dyn Read>, HashError> {
+        let decoder = if file_name == Path::new(STDIN_NAME) {
+            decompress(io::stdin().lock())
+        } else {
+            match File::open(file_name) {
+                Ok(f) => decompress(BufReader::new(f)),
+                Err(e) => return Err(HashError::FileOpenError(display_name(file_name), e))
+            }
+        };
+
+        return decoder.map_err(|e| HashError::ReadError(Some(display_name(file_name)), 0, e));
+    }
+}
+
+impl DataHasher for DecompressHasher {
+    fn hash_data(&mut self, r: &mut dyn Read) -> Result<String, HashError> {
+        return self.inner.hash_data(r);
+    }
+
+    fn hash_data_chunked(&mut self, r: &mut dyn Read, chunk_size: u64) -> Result<(String, Vec<String>), HashError> {
+        return self.inner.hash_data_chunked(r, chunk_size);
+    }
+
+    fn hash_data_tree(&mut self, r: &mut dyn Read, leaf_size: u64) -> Result<MerkleTree, HashError> {
+        return self.inner.hash_data_tree(r, leaf_size);
+    }
+}
+
+impl FileHash for DecompressHasher {
+    fn get_algo(&self) -> String {
+        return self.inner.get_algo();
+    }
+
+    fn verify_data(&mut self, r: &mut dyn Read, hash: &str) -> HashError {
+        return self.inner.verify_data(r, hash);
+    }
+
+    fn hash_file_chunked(&mut self, file_name: &Path, chunk_size: u64) -> Result<(String, Vec<String>), HashError> {
+        let mut r = DecompressHasher::open(file_name)?;
+
+        return self.inner.hash_data_chunked(&mut r, chunk_size).map_err(|e| e.in_file(file_name, 0));
+    }
+
+    fn hash_file_region(&mut self, file_name: &Path, region: &Region) -> Result<String, HashError> {
+        let mut r = DecompressHasher::open(file_name)?;
+
+        // The decompressed data can not seek
+        if let Err(e) = skip_to_region(&mut r, region) {
+            return Err(HashError::ReadError(Some(display_name(file_name)), 0, e));
+        }
+
+        return self.inner.hash_data(&mut RegionReader::new(r, region)).map_err(|e| e.in_file(file_name, region.offset));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs::{self, File};
+use std::path::Path;
+use crate::hasher::{from_hex_string, FileHash, HashError, Hasher, GIT_BLOB, GIT_TREE};
+use crate::escape::{display_name, name_bytes};
+
+// The hash of a directory tree is the hash of its canonical serialization. The
+// serialization consists of one record per entry below the root directory (the root
+// itself has no record). Entries are visited depth first, where the entries of each
+// directory are sorted by the bytes of their names and a directory is directly followed
+// by its contents. Each record has the form
+//
+// <type> <mode> <digest> <path>\0
+//
+// type:   'f' for regular files, 'd' for directories and 'l' for symbolic links
+// mode:   the permission bits as four octal digits if modes are included, '-' otherwise
+//         and always for symbolic links
+// digest: the hex hash of the file contents or of the link target, '-' for directories
+// path:   the path relative to the root directory with '/' as separator
+//
+// Symbolic links are not followed. Other file types can not be hashed.
+const RECORD_FILE: &str = "f";
+const RECORD_DIR: &str = "d";
+const RECORD_LINK: &str = "l";
+const NO_VALUE: &str = "-";
+
+#[cfg(unix)]
+fn mode_string(meta: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    return format!("{:04o}", meta.permissions().mode() & 0o7777);
+}
+
+#[cfg(not(unix))]
+fn mode_string(meta: &fs::Metadata) -> String {
+    return String::from(if meta.permissions().readonly() { "0444" } else { "0644" });
+}
+
+pub struct DirHasher<'a> {
+    hasher: &'a mut dyn FileHash,
+    include_modes: bool,
+    records: Vec<u8>
+}
+
+impl<'a> DirHasher<'a> {
+    pub fn new(hasher: &'a mut dyn FileHash, include_modes: bool) -> DirHasher<'a> {
+        return DirHasher {
+            hasher,
+            include_modes,
+            records: Vec::new()
+        }
+    }
+
+    fn add_record(&mut self, record_type: &str, meta: &fs::Metadata, digest: &str, rel_path: &[u8]) {
+        let mode = if self.include_modes && (record_type != RECORD_LINK) { mode_string(meta) } else { String::from(NO_VALUE) };
+
+        self.records.extend_from_slice(format!("{} {} {} ", record_type, mode, digest).as_bytes());
+        self.records.extend_from_slice(rel_path);
+        self.records.push(0);
+    }
+
+    fn walk(&mut self, dir: &Path, rel_dir: &[u8]) -> Result<(), HashError> {
+        let read_res = fs::read_dir(dir).map_err(|e| HashError::FileOpenError(display_name(dir), e))?;
+
+        let mut entries: Vec<(Vec<u8>, std::path::PathBuf)> = Vec::new();
+        for entry in read_res {
+            let entry = entry.map_err(|e| HashError::ReadError(Some(display_name(dir)), 0, e))?;
+            entries.push((name_bytes(&entry.file_name()), entry.path()));
+        }
+
+        entries.sort();
+
+        for (name, path) in entries {
+            let path_name = display_name(&path);
+            let meta = fs::symlink_metadata(&path).map_err(|e| HashError::FileOpenError(path_name.clone(), e))?;
+
+            let mut rel_path = rel_dir.to_vec();
+            if !rel_path.is_empty() {
+                rel_path.push(b'/');
+            }
+            rel_path.extend_from_slice(&name);
+
+            if meta.file_type().is_symlink() {
+                let target = fs::read_link(&path).map_err(|e| HashError::ReadError(Some(path_name.clone()), 0, e))?;
+                let digest = self.hasher.hash_data(&mut name_bytes(target.as_os_str()).as_slice())?;
+                self.add_record(RECORD_LINK, &meta, &digest, &rel_path);
+            } else if meta.is_dir() {
+                self.add_record(RECORD_DIR, &meta, NO_VALUE, &rel_path);
+                self.walk(&path, &rel_path)?;
+            } else if meta.is_file() {
+                let mut f = File::open(&path).map_err(|e| HashError::FileOpenError(path_name.clone(), e))?;
+                let digest = self.hasher.hash_data(&mut f).map_err(|e| e.in_file(&path, 0))?;
+                self.add_record(RECORD_FILE, &meta, &digest, &rel_path);
+            } else {
+                return Err(HashError::UnsupportedFileType(path_name));
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Returns the canonical serialization of the tree below root
+    pub fn serialize(&mut self, root: &Path) -> Result<Vec<u8>, HashError> {
+        self.records.clear();
+        self.walk(root, &[])?;
+
+        return Ok(std::mem::take(&mut self.records));
+    }
+
+    pub fn hash_dir(&mut self, root: &Path) -> Result<String, HashError> {
+        let records = self.serialize(root)?;
+
+        return self.hasher.hash_data(&mut records.as_slice());
+    }
+}
+
+// Git tree entries have the form <mode> <name>\0<raw object ID>
+const GIT_MODE_FILE: &str = "100644";
+const GIT_MODE_EXECUTABLE: &str = "100755";
+const GIT_MODE_LINK: &str = "120000";
+const GIT_MODE_TREE: &str = "40000";
+const GIT_DIR: &str = ".git";
+
+#[cfg(unix)]
+fn is_executable(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    return (meta.permissions().mode() & 0o100) != 0;
+}
+
+#[cfg(not(unix))]
+fn is_executable(_meta: &fs::Metadata) -> bool {
+    return false;
+}
+
+// Calculates the ID of the git tree object of a directory in the same way as git write-tree
+// does for an index containing all files below the directory. As git does not record empty
+// directories they are left out. The .git directory is ignored.
+pub struct GitTreeHasher<'a> {
+    hasher: &'a mut Hasher
+}
+
+impl<'a> GitTreeHasher<'a> {
+    // The hasher has to be created by Hasher::new_git
+    pub fn new(hasher: &'a mut Hasher) -> GitTreeHasher<'a> {
+        return GitTreeHasher { hasher };
+    }
+
+    // Returns None for directories which do not contain any files
+    fn tree_id(&mut self, dir: &Path) -> Result<Option<String>, HashError> {
+        let read_res = fs::read_dir(dir).map_err(|e| HashError::FileOpenError(display_name(dir), e))?;
+
+        // Git sorts the entries as if the names of trees ended with '/'
+        let mut entries: Vec<(Vec<u8>, Vec<u8>, &str, String)> = Vec::new();
+
+        for entry in read_res {
+            let entry = entry.map_err(|e| HashError::ReadError(Some(display_name(dir)), 0, e))?;
+            let name = name_bytes(&entry.file_name());
+            let path = entry.path();
+            let path_name = display_name(&path);
+            let meta = fs::symlink_metadata(&path).map_err(|e| HashError::FileOpenError(path_name.clone(), e))?;
+
+            if name == GIT_DIR.as_bytes() {
+                continue;
+            }
+
+            let mut sort_key = name.clone();
+
+            let (mode, id) = if meta.file_type().is_symlink() {
+                let target = fs::read_link(&path).map_err(|e| HashError::ReadError(Some(path_name.clone()), 0, e))?;
+                (GIT_MODE_LINK, self.hasher.hash_git_object(GIT_BLOB, &name_bytes(target.as_os_str()))?)
+            } else if meta.is_dir() {
+                sort_key.push(b'/');
+
+                match self.tree_id(&path)? {
+                    Some(id) => (GIT_MODE_TREE, id),
+                    None => continue
+                }
+            } else if meta.is_file() {
+                let mode = if is_executable(&meta) { GIT_MODE_EXECUTABLE } else { GIT_MODE_FILE };
+                (mode, self.hasher.hash_file(&path)?)
+            } else {
+                return Err(HashError::UnsupportedFileType(path_name));
+            };
+
+            entries.push((sort_key, name, mode, id));
+        }
+
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        entries.sort();
+        let mut tree: Vec<u8> = Vec::new();
+
+        for (_, name, mode, id) in entries {
+            tree.extend_from_slice(format!("{} ", mode).as_bytes());
+            tree.extend_from_slice(&name);
+            tree.push(0);
+            tree.extend_from_slice(&from_hex_string(&id).ok_or(HashError::GenericError)?);
+        }
+
+        return Ok(Some(self.hasher.hash_git_object(GIT_TREE, &tree)?));
+    }
+
+    pub fn hash_dir(&mut self, root: &Path) -> Result<String, HashError> {
+        return match self.tree_id(root)? {
+            Some(id) => Ok(id),
+            None => self.hasher.hash_git_object(GIT_TREE, &[])
+        };
+    }
+}
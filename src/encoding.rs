@@ -0,0 +1,160 @@
+use base64::Engine;
+
+// Hashes can be written in one of these encodings. base32 is the variant used by Nix, which
+// has its own alphabet and starts with the last bits of the hash. base58 uses the Bitcoin
+// alphabet.
+pub const ENCODING_HEX: &str = "hex";
+pub const ENCODING_BASE64: &str = "base64";
+pub const ENCODING_BASE32: &str = "base32";
+pub const ENCODING_BASE58: &str = "base58";
+
+const NIX_BASE32_CHARS: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+const BASE58_CHARS: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Hex,
+    Base64,
+    Base32,
+    Base58
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        return match name {
+            ENCODING_HEX => Some(Encoding::Hex),
+            ENCODING_BASE64 => Some(Encoding::Base64),
+            ENCODING_BASE32 => Some(Encoding::Base32),
+            ENCODING_BASE58 => Some(Encoding::Base58),
+            _ => None
+        };
+    }
+
+    pub fn encode(&self, data: &[u8]) -> String {
+        return match self {
+            Encoding::Hex => crate::hasher::to_hex_string(data),
+            Encoding::Base64 => base64::engine::general_purpose::STANDARD.encode(data),
+            Encoding::Base32 => encode_nix_base32(data),
+            Encoding::Base58 => encode_base58(data)
+        };
+    }
+
+    pub fn decode(&self, text: &str) -> Option<Vec<u8>> {
+        return match self {
+            Encoding::Hex => crate::hasher::from_hex_string(text),
+            Encoding::Base64 => base64::engine::general_purpose::STANDARD.decode(text).ok(),
+            Encoding::Base32 => decode_nix_base32(text),
+            Encoding::Base58 => decode_base58(text)
+        };
+    }
+}
+
+// Decodes a hash of hash_len bytes given in any of the encodings. The encodings are tried
+// in a fixed order and only a result of the expected length is accepted, which makes the
+// result unambiguous for the lengths of the supported hashes.
+pub fn decode_any(text: &str, hash_len: usize) -> Option<Vec<u8>> {
+    return [Encoding::Hex, Encoding::Base32, Encoding::Base64, Encoding::Base58].iter()
+        .filter_map(|e| e.decode(text))
+        .find(|d| d.len() == hash_len);
+}
+
+fn nix_base32_len(data_len: usize) -> usize {
+    return (data_len * 8).div_ceil(5);
+}
+
+fn encode_nix_base32(data: &[u8]) -> String {
+    let mut res = String::new();
+
+    for n in (0..nix_base32_len(data.len())).rev() {
+        let b = n * 5;
+        let (i, j) = (b / 8, b % 8);
+        let mut c = (data[i] as u16) >> j;
+
+        if i + 1 < data.len() {
+            c |= (data[i + 1] as u16) << (8 - j);
+        }
+
+        res.push(NIX_BASE32_CHARS[(c & 0x1f) as usize] as char);
+    }
+
+    return res;
+}
+
+fn decode_nix_base32(text: &str) -> Option<Vec<u8>> {
+    let data_len = text.len() * 5 / 8;
+
+    if nix_base32_len(data_len) != text.len() {
+        return None;
+    }
+
+    let mut res = vec![0u8; data_len];
+
+    for (n, c) in text.bytes().rev().enumerate() {
+        let digit = NIX_BASE32_CHARS.iter().position(|x| *x == c)? as u16;
+        let b = n * 5;
+        let (i, j) = (b / 8, b % 8);
+
+        res[i] |= (digit << j) as u8;
+
+        let carry = digit >> (8 - j);
+        if i + 1 < data_len {
+            res[i + 1] |= carry as u8;
+        } else if carry != 0 {
+            return None;
+        }
+    }
+
+    return Some(res);
+}
+
+fn encode_base58(data: &[u8]) -> String {
+    let zeros = data.iter().take_while(|b| **b == 0).count();
+    // Digits in base 58, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+
+    for b in &data[zeros..] {
+        let mut carry = *b as u32;
+
+        for d in digits.iter_mut() {
+            carry += (*d as u32) << 8;
+            *d = (carry % 58) as u8;
+            carry /= 58;
+        }
+
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let mut res = "1".repeat(zeros);
+    digits.iter().rev().for_each(|d| res.push(BASE58_CHARS[*d as usize] as char));
+
+    return res;
+}
+
+fn decode_base58(text: &str) -> Option<Vec<u8>> {
+    let zeros = text.bytes().take_while(|c| *c == b'1').count();
+    // Bytes of the result, least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+
+    for c in text.bytes().skip(zeros) {
+        let mut carry = BASE58_CHARS.iter().position(|x| *x == c)? as u32;
+
+        for b in bytes.iter_mut() {
+            carry += (*b as u32) * 58;
+            *b = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    let mut res = vec![0u8; zeros];
+    res.extend(bytes.iter().rev());
+
+    return Some(res);
+}
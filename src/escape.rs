@@ -0,0 +1,148 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+// File names which can not be written to a line of reference data as they are, are
+// escaped as done by coreutils: the line starts with a backslash and in the file name
+// a backslash is written as \\, a newline as \n and a carriage return as \r. In addition
+// to that every byte which is not part of valid UTF-8 and whitespace at the start or the
+// end of a name is written as \xHH, so that arbitrary file names can be stored. Whitespace
+// around names which are not escaped is ignored when parsing.
+pub const ESCAPE_MARKER: char = '\\';
+
+#[cfg(unix)]
+pub fn name_bytes(name: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    return name.as_bytes().to_vec();
+}
+
+#[cfg(not(unix))]
+pub fn name_bytes(name: &OsStr) -> Vec<u8> {
+    return name.to_string_lossy().as_bytes().to_vec();
+}
+
+#[cfg(unix)]
+pub fn path_from_bytes(name: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    return PathBuf::from(std::ffi::OsString::from_vec(name));
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(name: Vec<u8>) -> PathBuf {
+    return PathBuf::from(String::from_utf8_lossy(&name).to_string());
+}
+
+fn escape_char(c: char, res: &mut String) {
+    match c {
+        '\\' => res.push_str("\\\\"),
+        '\n' => res.push_str("\\n"),
+        '\r' => res.push_str("\\r"),
+        _ => res.push(c)
+    }
+}
+
+fn escape_hex(bytes: &[u8], res: &mut String) {
+    bytes.iter().for_each(|b| res.push_str(&format!("\\x{:02x}", b)));
+}
+
+// Whitespace at the start or the end of a name would be removed when parsing
+fn escape_whitespace(whitespace: &[u8], res: &mut String) {
+    for c in String::from_utf8_lossy(whitespace).chars() {
+        match c {
+            '\n' | '\r' => escape_char(c, res),
+            _ => escape_hex(c.encode_utf8(&mut [0u8; 4]).as_bytes(), res)
+        }
+    }
+}
+
+// Returns the escaped name and whether escaping was necessary
+pub fn escape_bytes(name: &[u8]) -> (String, bool) {
+    let mut res = String::new();
+
+    let lead = match name.utf8_chunks().next() {
+        Some(c) => c.valid().len() - c.valid().trim_start().len(),
+        None => 0
+    };
+
+    let trail = match name.utf8_chunks().last() {
+        Some(c) if c.invalid().is_empty() => c.valid().len() - c.valid().trim_end().len(),
+        _ => 0
+    }.min(name.len() - lead);
+
+    escape_whitespace(&name[..lead], &mut res);
+
+    for chunk in name[lead..name.len() - trail].utf8_chunks() {
+        chunk.valid().chars().for_each(|c| escape_char(c, &mut res));
+        escape_hex(chunk.invalid(), &mut res);
+    }
+
+    escape_whitespace(&name[name.len() - trail..], &mut res);
+
+    let needs_escape = res.as_bytes() != name;
+
+    return (res, needs_escape);
+}
+
+pub fn escape_name(name: &Path) -> (String, bool) {
+    return escape_bytes(&name_bytes(name.as_os_str()));
+}
+
+pub fn unescape_name(name: &str) -> Option<PathBuf> {
+    let mut res: Vec<u8> = Vec::new();
+    let mut chars = name.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            res.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        match chars.next()? {
+            '\\' => res.push(b'\\'),
+            'n' => res.push(b'\n'),
+            'r' => res.push(b'\r'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 {
+                    return None;
+                }
+
+                res.push(u8::from_str_radix(&hex, 16).ok()?);
+            },
+            _ => return None
+        }
+    }
+
+    return Some(path_from_bytes(res));
+}
+
+// The form in which a file name is shown in messages
+pub fn display_name(name: &Path) -> String {
+    let (escaped, needs_escape) = escape_name(name);
+
+    if needs_escape {
+        return format!("{}{}", ESCAPE_MARKER, escaped);
+    }
+
+    return escaped;
+}
+
+// Turns a line of reference data which is not valid UTF-8 into an escaped line. Only
+// file names can contain such data.
+pub fn escape_raw_line(line: &[u8]) -> String {
+    // In an already escaped line only the invalid bytes have to be escaped
+    if line.starts_with(&[ESCAPE_MARKER as u8]) {
+        let mut res = String::new();
+
+        for chunk in line.utf8_chunks() {
+            res.push_str(chunk.valid());
+            escape_hex(chunk.invalid(), &mut res);
+        }
+
+        return res;
+    }
+
+    let (escaped, _) = escape_bytes(line);
+
+    return format!("{}{}", ESCAPE_MARKER, escaped);
+}
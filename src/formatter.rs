@@ -1,10 +1,46 @@
 use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::escape::{self, ESCAPE_MARKER};
+use crate::encoding::Encoding;
+use crate::hasher::from_hex_string;
+
+// Hashes can be given in hex, base64, base32 or base58. A hash can be prefixed by the name
+// of its algorithm in the digest notation used by container tools, e.g. sha256:<hex>. The
+// prefix is kept and checked when the hash is compared, ignoring its case.
+const HASH_CHARS: &str = "(?:[A-Za-z0-9]+:)?[A-Za-z0-9+/=]+";
 
 pub trait HashLineFormatter {
-    fn format(&self, hash: &String, file_name: &String) -> String;
-    fn parse(&self, hash_line: &String) -> Result<(String, String), ParseError>; // (file_name, hash)
+    fn format(&self, hash: &str, file_name: &Path) -> String;
+    fn parse(&self, hash_line: &str) -> Result<(PathBuf, String), ParseError>; // (file_name, hash)
+}
+
+// Prepends the escape marker to a formatted line if the file name had to be escaped
+pub fn format_escaped(file_name: &Path, make_line: impl Fn(&str) -> String) -> String {
+    let (name, needs_escape) = escape::escape_name(file_name);
+
+    if needs_escape {
+        return format!("{}{}", ESCAPE_MARKER, make_line(&name));
+    }
+
+    return make_line(&name);
+}
+
+// Converts the file name of a parsed line back and undoes the escaping if the line
+// started with the escape marker. Whitespace around names which are not escaped is
+// removed, escaped names keep it.
+pub fn parse_name(file_name: &str, escaped: bool, hash_line: &str) -> Result<PathBuf, ParseError> {
+    if !escaped {
+        return Ok(PathBuf::from(file_name.trim()));
+    }
+
+    return match escape::unescape_name(file_name) {
+        Some(n) => Ok(n),
+        None => Err(ParseError::FormatError(hash_line.to_string()))
+    };
 }
 
+#[derive(Debug)]
 pub enum ParseError {
     FormatError(String),
 }
@@ -17,6 +53,14 @@ impl ParseError {
     }
 } 
 
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}", self.message());
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 pub struct SimpleFormatter {
     exp : regex::Regex
 }
@@ -24,31 +68,84 @@ pub struct SimpleFormatter {
 impl SimpleFormatter {
     pub fn new() -> SimpleFormatter {
         return SimpleFormatter {
-            exp: Regex::new(r"^([A-Fa-f0-9]+)  (.*)$").unwrap()
+            exp: Regex::new(&format!(r"^({})  (.*)$", HASH_CHARS)).unwrap()
         }
     }
 }
 
 impl HashLineFormatter for SimpleFormatter {
-    fn format(&self, hash: &String, file_name: &String) -> String {
-        return format!("{}  {}", hash, file_name);
+    fn format(&self, hash: &str, file_name: &Path) -> String {
+        return format_escaped(file_name, |name| format!("{}  {}", hash, name));
     }
 
-    fn parse(&self, hash_line: &String) -> Result<(String, String), ParseError> {
-        let matches: Vec<regex::Captures> = self.exp.captures_iter(hash_line).collect();
+    fn parse(&self, hash_line: &str) -> Result<(PathBuf, String), ParseError> {
+        let escaped = hash_line.starts_with(ESCAPE_MARKER);
+        let line = if escaped { &hash_line[1..] } else { hash_line };
+        let matches: Vec<regex::Captures> = self.exp.captures_iter(line).collect();
 
         if matches.len() != 1 {
-            return Err(ParseError::FormatError(hash_line.clone()));
+            return Err(ParseError::FormatError(hash_line.to_string()));
         }
 
         let groups = &matches[0];
         let hash_val = &groups[1];
         let file_name = &groups[2];
 
-        return Ok((String::from(file_name.trim()), String::from(hash_val)));
+        return Ok((parse_name(file_name, escaped, hash_line)?, hash_val.to_string()));
     }    
 }
 
+// Writes the hashes calculated in hex in another encoding. Parsing is done by the
+// wrapped formatter.
+pub struct EncodedFormatter {
+    inner: Rc<dyn HashLineFormatter>,
+    encoding: Encoding
+}
+
+impl EncodedFormatter {
+    pub fn new(inner: Rc<dyn HashLineFormatter>, encoding: Encoding) -> EncodedFormatter {
+        return EncodedFormatter { inner, encoding };
+    }
+}
+
+impl HashLineFormatter for EncodedFormatter {
+    fn format(&self, hash: &str, file_name: &Path) -> String {
+        let encoded = match from_hex_string(hash) {
+            Some(data) => self.encoding.encode(&data),
+            None => hash.to_string()
+        };
+
+        return self.inner.format(&encoded, file_name);
+    }
+
+    fn parse(&self, hash_line: &str) -> Result<(PathBuf, String), ParseError> {
+        return self.inner.parse(hash_line);
+    }
+}
+
+// Writes hashes in digest notation, i.e. as <algorithm>:<hash>. Parsing is done by the
+// wrapped formatter.
+pub struct DigestFormatter {
+    inner: Rc<dyn HashLineFormatter>,
+    algo: String
+}
+
+impl DigestFormatter {
+    pub fn new(inner: Rc<dyn HashLineFormatter>, algo: &str) -> DigestFormatter {
+        return DigestFormatter { inner, algo: algo.to_lowercase() };
+    }
+}
+
+impl HashLineFormatter for DigestFormatter {
+    fn format(&self, hash: &str, file_name: &Path) -> String {
+        return self.inner.format(&format!("{}:{}", self.algo, hash), file_name);
+    }
+
+    fn parse(&self, hash_line: &str) -> Result<(PathBuf, String), ParseError> {
+        return self.inner.parse(hash_line);
+    }
+}
+
 pub struct BsdFormatter {
     algo_name: String,
     exp: regex::Regex
@@ -62,33 +159,35 @@ impl BsdFormatter {
         return BsdFormatter::new(&n);
     }
 
-    pub fn new(name: &String) -> BsdFormatter {
-        let exp_str = format!("^{} \\((.*)\\) = ([A-Fa-f0-9]+)$", name);
+    pub fn new(name: &str) -> BsdFormatter {
+        let exp_str = format!("^{} \\((.*)\\) = ({})$", name, HASH_CHARS);
 
         return BsdFormatter {
-            algo_name: name.clone(),
+            algo_name: name.to_string(),
             exp: Regex::new(&exp_str).unwrap()
         }
     }
 }
 
 impl HashLineFormatter for BsdFormatter {
-    fn format(&self, hash: &String, file_name: &String) -> String {
-        return format!("{} ({}) = {}", self.algo_name, file_name, hash);
+    fn format(&self, hash: &str, file_name: &Path) -> String {
+        return format_escaped(file_name, |name| format!("{} ({}) = {}", self.algo_name, name, hash));
     }
 
-    fn parse(&self, hash_line: &String) -> Result<(String, String), ParseError> {
-        let matches: Vec<regex::Captures> = self.exp.captures_iter(hash_line).collect();
+    fn parse(&self, hash_line: &str) -> Result<(PathBuf, String), ParseError> {
+        let escaped = hash_line.starts_with(ESCAPE_MARKER);
+        let line = if escaped { &hash_line[1..] } else { hash_line };
+        let matches: Vec<regex::Captures> = self.exp.captures_iter(line).collect();
 
         if matches.len() != 1 {
-            return Err(ParseError::FormatError(hash_line.clone()));
+            return Err(ParseError::FormatError(hash_line.to_string()));
         }
 
         let groups = &matches[0];
         let hash_val = &groups[2];
         let file_name = &groups[1];
 
-        return Ok((String::from(file_name.trim()), String::from(hash_val)));
+        return Ok((parse_name(file_name, escaped, hash_line)?, hash_val.to_string()));
     }     
 }
 
@@ -1,25 +1,43 @@
 use std::io::prelude::*;
 use std::fs::File;
-use digest::DynDigest;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::error::Error;
+use std::fmt;
+use subtle::ConstantTimeEq;
+use crate::encoding::decode_any;
+use crate::escape::display_name;
+use digest::{DynDigest, InvalidBufferSize};
+use crate::merkle::MerkleTree;
+use crate::region::{region_eof_error, skip_to_region, Region, RegionReader};
 
 #[allow(dead_code)]
+#[derive(Debug)]
 pub enum HashError {
     Ok,
     GenericError,
     HashVerifyFail(String),
     HashDifferent,
-    ReadError,
-    FileOpenError(String)
+    // Name of the file if known, offset at which reading failed and the cause
+    ReadError(Option<String>, u64, io::Error),
+    FileOpenError(String, io::Error),
+    UnsupportedFileType(String),
+    // Algorithm and reference hash
+    WrongHashLength(String, String),
+    StoredHashMissing(String),
+    // Name of the file and the stored value
+    StoredHashInvalid(String, String),
+    DuplicateEntry(String)
 }
 
 const HEX_CHARS: &str = "0123456789abcdef";
 
-fn to_hex_string(hash_val: Box<[u8]>) -> String {
+pub fn to_hex_string(hash_val: &[u8]) -> String {
     let mut res = String::new();
 
-    for i in 0..hash_val.len() {
-        let hi_nibble = (hash_val[i] & 0xF0u8) >> 4;
-        let lo_nibble = hash_val[i] & 0x0Fu8;
+    for b in hash_val {
+        let hi_nibble = (b & 0xF0u8) >> 4;
+        let lo_nibble = b & 0x0Fu8;
         res.push(HEX_CHARS.as_bytes()[usize::from(hi_nibble)] as char);
         res.push(HEX_CHARS.as_bytes()[usize::from(lo_nibble)] as char);
     }
@@ -27,36 +45,201 @@ fn to_hex_string(hash_val: Box<[u8]>) -> String {
     return res;
 }
 
+pub fn from_hex_string(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    return hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let hi_nibble = (pair[0] as char).to_digit(16)?;
+            let lo_nibble = (pair[1] as char).to_digit(16)?;
+            Some(((hi_nibble << 4) | lo_nibble) as u8)
+        })
+        .collect();
+}
+
+// Compares a reference hash in any of the supported encodings with a calculated hash in hex.
+// The decoded bytes are compared in constant time. A reference hash in digest notation
+// <algorithm>:<hash> has to name the algorithm which was used.
+pub fn compare_hashes(algo: &str, reference: &str, calculated: &str) -> HashError {
+    let calc_bytes = match from_hex_string(calculated) {
+        Some(c) => c,
+        None => return HashError::GenericError
+    };
+
+    let encoded = match reference.split_once(':') {
+        Some((prefix, hash)) if prefix.eq_ignore_ascii_case(algo) => hash,
+        Some(_) => return HashError::WrongHashLength(String::from(algo), String::from(reference)),
+        None => reference
+    };
+
+    let ref_bytes = match decode_any(encoded, calc_bytes.len()) {
+        Some(r) => r,
+        None => return HashError::WrongHashLength(String::from(algo), String::from(reference))
+    };
+
+    if bool::from(ref_bytes.ct_eq(&calc_bytes)) {
+        return HashError::Ok;
+    }
+
+    return HashError::HashDifferent;
+}
+
 impl HashError {
     pub fn message(&self) -> String {
         match self {
             HashError::GenericError => "Operation failed".to_string(),
-            HashError::FileOpenError(file_name) => format!("Error opening file '{}'", file_name),
+            HashError::FileOpenError(file_name, e) => format!("Error opening file '{}': {}", file_name, e),
             HashError::Ok => "OK".to_string(),
             HashError::HashDifferent => "Hashes different".to_string(),
             HashError::HashVerifyFail(file_name) => format!("Hash verification for file '{}' failed", file_name),
-            HashError::ReadError => "Unable to read data".to_string()
+            HashError::ReadError(Some(file_name), offset, e) => format!("Unable to read file '{}' at offset {}: {}", file_name, offset, e),
+            HashError::ReadError(None, offset, e) => format!("Unable to read data at offset {}: {}", offset, e),
+            HashError::UnsupportedFileType(file_name) => format!("File '{}' has an unsupported type", file_name),
+            HashError::WrongHashLength(algo, hash) => format!("Reference hash '{}' is not a {} hash in a supported encoding", hash, algo),
+            HashError::StoredHashMissing(file_name) => format!("File '{}' has no stored hash", file_name),
+            HashError::StoredHashInvalid(file_name, value) => format!("Stored hash '{}' of file '{}' can not be used", value, file_name),
+            HashError::DuplicateEntry(file_name) => format!("File '{}' is listed more than once", file_name)
         }
     }
+
+    // Adds the name of the file to a ReadError which occurred while reading its data
+    // from offset start on
+    pub fn in_file(self, file_name: &Path, start: u64) -> HashError {
+        return match self {
+            HashError::ReadError(None, offset, e) => HashError::ReadError(Some(display_name(file_name)), start + offset, e),
+            other => other
+        };
+    }
 } 
 
+impl fmt::Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.message());
+    }
+}
+
+impl Error for HashError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return match self {
+            HashError::ReadError(_, _, e) | HashError::FileOpenError(_, e) => Some(e),
+            _ => None
+        };
+    }
+}
+
 const BUFFER_SIZE: usize = 4096;
 
+// As for coreutils this file name refers to the data read from stdin
+pub const STDIN_NAME: &str = "-";
+
+type ChunkDigests = (Box<[u8]>, Vec<Box<[u8]>>);
+
 pub trait DataHasher {
     fn hash_data(&mut self, r: &mut dyn Read)-> Result<String, HashError>;
+    // Returns the hash over all data and the hashes of the consecutive chunk_size byte chunks
+    fn hash_data_chunked(&mut self, r: &mut dyn Read, chunk_size: u64)-> Result<(String, Vec<String>), HashError>;
+    fn hash_data_tree(&mut self, r: &mut dyn Read, leaf_size: u64)-> Result<MerkleTree, HashError>;
 }
 
 pub trait FileHash : DataHasher {
     fn get_algo(&self) -> String;
-    fn verify_data(&mut self, r: &mut dyn Read, hash: &String)-> HashError;
-    fn hash_file(&mut self, file_name: &String) -> Result<String, HashError>;
-    fn verify_file(&mut self, file_name: &String, hash: &String) -> HashError;
+    fn verify_data(&mut self, r: &mut dyn Read, hash: &str)-> HashError;
+    fn hash_file_chunked(&mut self, file_name: &Path, chunk_size: u64) -> Result<(String, Vec<String>), HashError>;
+    fn hash_file_region(&mut self, file_name: &Path, region: &Region) -> Result<String, HashError>;
+
+    fn hash_file(&mut self, file_name: &Path) -> Result<String, HashError> {
+        return self.hash_file_region(file_name, &Region::default());
+    }
+
+    fn verify_file(&mut self, file_name: &Path, hash: &str) -> HashError {
+        return self.verify_file_region(file_name, &Region::default(), hash);
+    }
+
+    fn verify_file_region(&mut self, file_name: &Path, region: &Region, hash: &str) -> HashError {
+        let hash_res = match self.hash_file_region(file_name, region) {
+            Ok(hash_val) => hash_val,
+            Err(err_val) => return err_val,
+        };
+
+        return match compare_hashes(&self.get_algo(), hash, &hash_res) {
+            HashError::HashDifferent => HashError::HashVerifyFail(display_name(file_name)),
+            res => res
+        };
+    }
+
+    // Returns the names under which the data of a file is recorded in reference data
+    fn expand_name(&mut self, file_name: &Path) -> Result<Vec<PathBuf>, HashError> {
+        return Ok(vec![file_name.to_path_buf()]);
+    }
+}
+
+// Calculates the hashes of several algorithms in one pass over the data. The result is the
+// concatenation of the hashes in the order of the algorithms.
+pub struct MultiDigest {
+    digests: Vec<Box<dyn DynDigest>>
+}
+
+impl MultiDigest {
+    pub fn new(digests: Vec<Box<dyn DynDigest>>) -> MultiDigest {
+        return MultiDigest { digests };
+    }
+}
+
+impl DynDigest for MultiDigest {
+    fn update(&mut self, data: &[u8]) {
+        self.digests.iter_mut().for_each(|d| d.update(data));
+    }
+
+    fn finalize_into(mut self, buf: &mut [u8]) -> Result<(), InvalidBufferSize> {
+        return self.finalize_into_reset(buf);
+    }
+
+    fn finalize_into_reset(&mut self, out: &mut [u8]) -> Result<(), InvalidBufferSize> {
+        if out.len() != self.output_size() {
+            return Err(InvalidBufferSize);
+        }
+
+        let mut pos: usize = 0;
+
+        for d in self.digests.iter_mut() {
+            let size = d.output_size();
+            d.finalize_into_reset(&mut out[pos..pos + size])?;
+            pos += size;
+        }
+
+        return Ok(());
+    }
+
+    fn reset(&mut self) {
+        self.digests.iter_mut().for_each(|d| d.reset());
+    }
+
+    fn output_size(&self) -> usize {
+        return self.digests.iter().map(|d| d.output_size()).sum();
+    }
+
+    fn box_clone(&self) -> Box<dyn DynDigest> {
+        return Box::new(MultiDigest::new(self.digests.iter().map(|d| d.box_clone()).collect()));
+    }
+}
+
+// Git hashes an object as <type> <size in decimal>\0<content>
+pub const GIT_BLOB: &str = "blob";
+pub const GIT_TREE: &str = "tree";
+
+fn git_header(object_type: &str, size: u64) -> Vec<u8> {
+    return format!("{} {}\0", object_type, size).into_bytes();
 }
 
 pub struct Hasher {
     algo_name: String,
     hash_impl: Box<dyn DynDigest>,
-    buffer: [u8; BUFFER_SIZE]
+    buffer: [u8; BUFFER_SIZE],
+    tree_leaf_size: Option<u64>,
+    git_blobs: bool
 } 
 
 impl Hasher {
@@ -64,70 +247,226 @@ impl Hasher {
         let res = Hasher {
                     algo_name: String::from(name),
                     hash_impl: d,
-                    buffer: [0; BUFFER_SIZE]
+                    buffer: [0; BUFFER_SIZE],
+                    tree_leaf_size: None,
+                    git_blobs: false
                 }; 
 
         return res;
     }
-}
 
-impl DataHasher for Hasher {
-    fn hash_data(&mut self, r: &mut dyn Read) -> Result<String, HashError> {
+    // A Hasher which returns the root of the MerkleTree over leaves of leaf_size bytes
+    // instead of a plain hash
+    pub fn new_tree(name: &str, d: Box<dyn DynDigest>, leaf_size: u64) -> Hasher {
+        let mut res = Hasher::new(name, d);
+        res.tree_leaf_size = Some(leaf_size);
+
+        return res;
+    }
+
+    // A Hasher which returns the ID of a git blob object with the data instead of a plain hash
+    pub fn new_git(name: &str, d: Box<dyn DynDigest>) -> Hasher {
+        let mut res = Hasher::new(name, d);
+        res.git_blobs = true;
+
+        return res;
+    }
+
+    // Returns the ID of the git object of the given type with the given content
+    pub fn hash_git_object(&mut self, object_type: &str, data: &[u8]) -> Result<String, HashError> {
+        return self.hash_stream(&git_header(object_type, data.len() as u64), &mut &data[..]);
+    }
+
+    // Returns the hash of the prefix followed by the data read from r
+    fn hash_stream(&mut self, prefix: &[u8], r: &mut dyn Read) -> Result<String, HashError> {
         self.hash_impl.reset();
+        self.hash_impl.update(prefix);
+        let mut total: u64 = 0;
 
         loop {
             let data_read =  r.read(&mut self.buffer);
             match data_read {
                 Ok(0) => {
                     let hash_val = self.hash_impl.finalize_reset();
-                    return Ok(to_hex_string(hash_val));
+                    return Ok(to_hex_string(&hash_val));
+                },
+                Ok(bytes_read) => {
+                    self.hash_impl.update(&self.buffer[..bytes_read]);
+                    total += bytes_read as u64;
                 },
-                Ok(bytes_read) => self.hash_impl.update(&self.buffer[..bytes_read]),
-                Err(_) => return Err(HashError::ReadError)
+                Err(e) => return Err(HashError::ReadError(None, total, e))
+            }
+        }
+    }
+
+    // Returns the raw hash over all data and the raw hashes of the chunks
+    fn digest_chunks(&mut self, r: &mut dyn Read, chunk_size: u64) -> Result<ChunkDigests, HashError> {
+        let mut chunk_impl = self.hash_impl.box_clone();
+        let mut chunk_hashes: Vec<Box<[u8]>> = Vec::new();
+        let mut chunk_fill: u64 = 0;
+        let mut total: u64 = 0;
+
+        self.hash_impl.reset();
+        chunk_impl.reset();
+
+        loop {
+            let bytes_read = match r.read(&mut self.buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => return Err(HashError::ReadError(None, total, e))
+            };
+
+            total += bytes_read as u64;
+
+            self.hash_impl.update(&self.buffer[..bytes_read]);
+
+            // Split the buffer at chunk boundaries
+            let mut pos: usize = 0;
+            while pos < bytes_read {
+                let part_len = std::cmp::min((chunk_size - chunk_fill) as usize, bytes_read - pos);
+                chunk_impl.update(&self.buffer[pos..pos + part_len]);
+                chunk_fill += part_len as u64;
+                pos += part_len;
+
+                if chunk_fill == chunk_size {
+                    chunk_hashes.push(chunk_impl.finalize_reset());
+                    chunk_fill = 0;
+                }
+            }
+        }
+
+        if chunk_fill != 0 {
+            chunk_hashes.push(chunk_impl.finalize_reset());
+        }
+
+        return Ok((self.hash_impl.finalize_reset(), chunk_hashes));
+    }
+
+    fn make_tree(&mut self, leaves: Vec<Box<[u8]>>) -> MerkleTree {
+        let mut leaves = leaves;
+
+        // Empty data consists of one empty leaf
+        if leaves.is_empty() {
+            self.hash_impl.reset();
+            leaves.push(self.hash_impl.finalize_reset());
+        }
+
+        return MerkleTree::new(leaves, self.hash_impl.as_mut());
+    }
+}
+
+impl DataHasher for Hasher {
+    fn hash_data(&mut self, r: &mut dyn Read) -> Result<String, HashError> {
+        if let Some(leaf_size) = self.tree_leaf_size {
+            let tree = self.hash_data_tree(r, leaf_size)?;
+            return Ok(to_hex_string(tree.root()));
+        }
+
+        // The size of the data is part of the git object header, so it has to be read first
+        if self.git_blobs {
+            let mut data: Vec<u8> = Vec::new();
+
+            if let Err(e) = r.read_to_end(&mut data) {
+                return Err(HashError::ReadError(None, data.len() as u64, e));
             }
+
+            return self.hash_git_object(GIT_BLOB, &data);
         }
+
+        return self.hash_stream(&[], r);
+    }
+
+    fn hash_data_chunked(&mut self, r: &mut dyn Read, chunk_size: u64) -> Result<(String, Vec<String>), HashError> {
+        // Chunk hashes are not defined in tree and git mode
+        if self.tree_leaf_size.is_some() || self.git_blobs {
+            return Err(HashError::GenericError);
+        }
+
+        let (whole_hash, chunk_hashes) = self.digest_chunks(r, chunk_size)?;
+
+        return Ok((to_hex_string(&whole_hash), chunk_hashes.iter().map(|h| to_hex_string(h)).collect()));
+    }
+
+    fn hash_data_tree(&mut self, r: &mut dyn Read, leaf_size: u64) -> Result<MerkleTree, HashError> {
+        let (_, leaves) = self.digest_chunks(r, leaf_size)?;
+
+        return Ok(self.make_tree(leaves));
     }
 }
 
 impl FileHash for Hasher
 {
     fn get_algo(&self) -> String {
-        return self.algo_name.clone();
+        if self.git_blobs {
+            return format!("GIT-{}", self.algo_name);
+        }
+
+        return match self.tree_leaf_size {
+            Some(leaf_size) => format!("{}-TREE-{}", self.algo_name, leaf_size),
+            None => self.algo_name.clone()
+        };
     }
 
-    fn verify_data(&mut self, r: &mut dyn Read, hash: &String)-> HashError {
+    fn verify_data(&mut self, r: &mut dyn Read, hash: &str)-> HashError {
         let hash_res = match self.hash_data(r) {
             Ok(hash_val) => hash_val,
             Err(err_val) => return err_val,
         };
 
-        if hash != &hash_res {
-            return HashError::HashDifferent;
-        }
-
-        return HashError::Ok;        
+        return compare_hashes(&self.get_algo(), hash, &hash_res);
     } 
 
-    fn hash_file(&mut self, file_name: &String) -> Result<String, HashError> {
+    fn hash_file_chunked(&mut self, file_name: &Path, chunk_size: u64) -> Result<(String, Vec<String>), HashError> {
+        if file_name == Path::new(STDIN_NAME) {
+            return self.hash_data_chunked(&mut io::stdin().lock(), chunk_size).map_err(|e| e.in_file(file_name, 0));
+        }
+
         let mut f = match File::open(file_name) {
             Ok(file) => file,
-            Err(_) => return Err(HashError::FileOpenError(file_name.clone())),
+            Err(e) => return Err(HashError::FileOpenError(display_name(file_name), e)),
         };
 
-        return self.hash_data(&mut f);
+        return self.hash_data_chunked(&mut f, chunk_size).map_err(|e| e.in_file(file_name, 0));
     }
 
-    fn verify_file(&mut self, file_name: &String, hash: &String) -> HashError {
-        let hash_res = match self.hash_file(file_name) {
-            Ok(hash_val) => hash_val,
-            Err(err_val) => return err_val,
+    fn hash_file_region(&mut self, file_name: &Path, region: &Region) -> Result<String, HashError> {
+        if file_name == Path::new(STDIN_NAME) {
+            let mut stdin = io::stdin().lock();
+
+            // stdin can not seek
+            if let Err(e) = skip_to_region(&mut stdin, region) {
+                return Err(HashError::ReadError(Some(display_name(file_name)), 0, e));
+            }
+
+            return self.hash_data(&mut RegionReader::new(stdin, region)).map_err(|e| e.in_file(file_name, region.offset));
+        }
+
+        let mut f = match File::open(file_name) {
+            Ok(file) => file,
+            Err(e) => return Err(HashError::FileOpenError(display_name(file_name), e)),
         };
 
-        if hash != &hash_res {
-            return HashError::HashVerifyFail(file_name.clone());
+        if region.is_whole() {
+            // Files do not have to be read into memory to determine their size
+            if self.git_blobs {
+                let size = f.metadata().map_err(|e| HashError::ReadError(Some(display_name(file_name)), 0, e))?.len();
+                return self.hash_stream(&git_header(GIT_BLOB, size), &mut f).map_err(|e| e.in_file(file_name, 0));
+            }
+
+            return self.hash_data(&mut f).map_err(|e| e.in_file(file_name, 0));
         }
 
-        return HashError::Ok;
+        // Seeking past the end of a file does not fail
+        let size = f.metadata().map_err(|e| HashError::ReadError(Some(display_name(file_name)), 0, e))?.len();
+        if size < region.offset {
+            return Err(HashError::ReadError(Some(display_name(file_name)), size, region_eof_error()));
+        }
+
+        if let Err(e) = f.seek(SeekFrom::Start(region.offset)) {
+            return Err(HashError::ReadError(Some(display_name(file_name)), region.offset, e));
+        }
+
+        return self.hash_data(&mut RegionReader::new(f, region)).map_err(|e| e.in_file(file_name, region.offset));
     }
 }
 
@@ -1,183 +1,1444 @@
-use sha2::{Sha256, Sha512, Digest};
+
+use sha2::{Sha256, Sha384, Sha512, Digest};
 use digest::DynDigest;
 use std::fs::File;
 use clap::{Arg, Command};
 use std::rc::Rc;
 use std::process;
-use std::io::{self, Read, BufRead};
+use std::io::{self, Read, BufRead, Write};
+use std::path::{Path, PathBuf};
 
 mod tests;
 mod hasher;
 mod formatter;
 mod reffile;
+mod signature;
+mod chunked;
+mod merkle;
+mod dirhash;
+mod cdc;
+mod region;
+mod escape;
+mod encoding;
+mod compress;
+mod archive;
+#[cfg(target_os = "linux")]
+mod watch;
+mod scrub;
+mod xattrs;
+mod sri;
+mod oci;
 
 use hasher::Hasher;
 use hasher::FileHash;
+use hasher::DataHasher;
 use hasher::HashError;
+use hasher::MultiDigest;
 use formatter::HashLineFormatter;
-use reffile::RefFile;
+use reffile::{RefFile, RefFileError};
+use chunked::ChunkedRefFile;
+use region::Region;
+use escape::display_name;
 
+const ALGO_SHA1: &str = "SHA1";
 const ALGO_SHA256: &str = "SHA256";
+const ALGO_SHA384: &str = "SHA384";
 const ALGO_SHA512: &str = "SHA512";
+const ALGO_BLAKE3: &str = "BLAKE3";
+// Names of the algorithms on the command line
+const ALGO_ARG_SHA1: &str = "sha1";
+const ALGO_ARG_SHA256: &str = "sha256";
+const ALGO_ARG_SHA384: &str = "sha384";
+const ALGO_ARG_SHA512: &str = "sha512";
+const ALGO_ARG_BLAKE3: &str = "blake3";
+// Exit codes, see README.md. When several errors occur the one which comes last in
+// STATUS_SEVERITY determines the exit code.
 const PROG_RETURN_OK: i32 = 0;
+const PROG_RETURN_MISMATCH: i32 = 1;
+const PROG_RETURN_USAGE: i32 = 2;
+const PROG_RETURN_MISSING: i32 = 3;
+const PROG_RETURN_UNREADABLE: i32 = 4;
+const PROG_RETURN_MALFORMED: i32 = 5;
 const PROG_RETURN_ERR: i32 = 42;
 
+const STATUS_SEVERITY: [i32; 7] = [PROG_RETURN_OK, PROG_RETURN_MISMATCH, PROG_RETURN_MISSING, PROG_RETURN_UNREADABLE,
+    PROG_RETURN_MALFORMED, PROG_RETURN_ERR, PROG_RETURN_USAGE];
+
+// Returns the exit status of the more severe of two results
+fn worse_status(a: i32, b: i32) -> i32 {
+    let severity = |status: i32| STATUS_SEVERITY.iter().position(|x| *x == status).unwrap_or(STATUS_SEVERITY.len());
+
+    return if severity(b) > severity(a) { b } else { a };
+}
+
+fn io_status(e: &io::Error) -> i32 {
+    return match e.kind() {
+        io::ErrorKind::NotFound => PROG_RETURN_MISSING,
+        _ => PROG_RETURN_UNREADABLE
+    };
+}
+
+fn error_status(e: &HashError) -> i32 {
+    return match e {
+        HashError::Ok => PROG_RETURN_OK,
+        HashError::HashDifferent | HashError::HashVerifyFail(_) => PROG_RETURN_MISMATCH,
+        HashError::FileOpenError(_, io_err) => io_status(io_err),
+        HashError::ReadError(_, _, _) | HashError::UnsupportedFileType(_) => PROG_RETURN_UNREADABLE,
+        HashError::StoredHashMissing(_) => PROG_RETURN_MISSING,
+        HashError::WrongHashLength(_, _) | HashError::StoredHashInvalid(_, _) | HashError::DuplicateEntry(_) => PROG_RETURN_MALFORMED,
+        HashError::GenericError => PROG_RETURN_ERR
+    };
+}
+
+
+// Destination of the reference data for one algorithm. When several algorithms are used the
+// hasher returns the concatenation of their hashes and every output uses its part.
+struct ManifestOutput {
+    formatter: Rc<dyn HashLineFormatter>,
+    hash_part: std::ops::Range<usize>,
+    writer: Box<dyn Write>
+}
+
+impl ManifestOutput {
+    fn write_entry(&mut self, hash: &str, file_name: &Path, chunk_lines: &[String], region: &Region) -> io::Result<()> {
+        writeln!(self.writer, "{}", self.formatter.format(&hash[self.hash_part.clone()], file_name))?;
+
+        for l in chunk_lines {
+            writeln!(self.writer, "{}", l)?;
+        }
+
+        if !region.is_whole() {
+            writeln!(self.writer, "{}", region.format())?;
+        }
+
+        return Ok(());
+    }
+}
+
+// Returns the number of hashed files and the exit status. Files which can not be hashed
+// are reported and skipped unless fail_fast is set. A file can lead to several entries
+// in the reference data, e.g. an archive in archive mode.
+fn hash_files<T>(file_names: T, h: &mut dyn FileHash, outputs: &mut [ManifestOutput], chunk_size: Option<u64>, region: &Region, fail_fast: bool) -> (u32, i32)
+where 
+    T: IntoIterator<Item=PathBuf>
+{
+    let mut count: u32 = 0;
+    let mut status = PROG_RETURN_OK;
+
+    for name in file_names {
+        let expanded = match h.expand_name(&name) {
+            Ok(names) => names,
+            Err(err) => {
+                eprintln!("{}", err.message()); 
+                status = worse_status(status, error_status(&err));
+
+                if fail_fast {
+                    return (count, status);
+                }
+
+                continue;
+            }
+        };
+
+        let (file_count, file_status) = hash_expanded_files(expanded, h, outputs, chunk_size, region, fail_fast);
+        count += file_count;
+        status = worse_status(status, file_status);
+
+        if fail_fast && (status != PROG_RETURN_OK) {
+            return (count, status);
+        }
+    }
+
+    return (count, status);
+}
+
+fn hash_expanded_files(file_names: Vec<PathBuf>, h: &mut dyn FileHash, outputs: &mut [ManifestOutput], chunk_size: Option<u64>, region: &Region, fail_fast: bool) -> (u32, i32) {
+    let mut count: u32 = 0;
+    let mut status = PROG_RETURN_OK;
+
+    for i in file_names {
+        let hash_res = match chunk_size {
+            Some(size) => h.hash_file_chunked(&i, size),
+            None => h.hash_file_region(&i, region).map(|hash| (hash, Vec::new()))
+        };
+
+        let (hash, chunk_hashes) = match hash_res {
+            Ok(val) => val,
+            Err(err) => {
+                eprintln!("{}", err.message()); 
+                status = worse_status(status, error_status(&err));
+
+                if fail_fast {
+                    return (count, status);
+                }
+
+                continue;
+            }
+        };
+
+        let chunk_lines = match chunk_size {
+            Some(size) => chunked::format_chunks(size, &chunk_hashes),
+            None => Vec::new()
+        };
+
+        for o in outputs.iter_mut() {
+            if let Err(e) = o.write_entry(&hash, &i, &chunk_lines, region) {
+                eprintln!("Unable to write reference data: {}", e);
+                return (count, PROG_RETURN_ERR);
+            }
+        }
+
+        count += 1;
+    }
+
+    return (count, status);
+}
+
+// Hashes the files whose names are read from r. The names are separated by separator.
+// If the names are read from stdin, the name - can not refer to stdin at the same time.
+#[allow(clippy::too_many_arguments)]
+fn hash_listed_files<R : BufRead>(r: R, separator: u8, names_from_stdin: bool, h: &mut dyn FileHash, outputs: &mut [ManifestOutput], chunk_size: Option<u64>, region: &Region, fail_fast: bool) -> (u32, i32) {
+    let names_status = std::cell::Cell::new(PROG_RETURN_OK);
+
+    let names = r.split(separator).map_while(|x| match x {
+        Ok(name) => Some(name),
+        Err(e) => {
+            eprintln!("{}", e);
+            names_status.set(worse_status(names_status.get(), PROG_RETURN_UNREADABLE));
+            None
+        }
+    }).filter_map(|mut name| {
+        if (separator == b'\n') && name.ends_with(b"\r") {
+            name.pop();
+        }
+
+        if name.is_empty() {
+            return None;
+        }
+
+        if names_from_stdin && (name == hasher::STDIN_NAME.as_bytes()) {
+            eprintln!("Data to hash can not be read from stdin when file names are read from stdin");
+            names_status.set(worse_status(names_status.get(), PROG_RETURN_USAGE));
+            return None;
+        }
+
+        return Some(escape::path_from_bytes(name));
+    }).take_while(|_| !fail_fast || (names_status.get() == PROG_RETURN_OK));
+
+    let (count, status) = hash_files(names, h, outputs, chunk_size, region, fail_fast);
+
+    return (count, worse_status(status, names_status.get()));
+}
+
+// Returns the exit status
+pub fn process_one_file(hasher: &mut dyn FileHash, ref_data: (&Path, &String, &Region)) -> i32 {
+    let (file_name, hash_val, region) = ref_data;
+    let shown_name = display_name(file_name);
+
+    let verify_result = hasher.verify_file_region(file_name, region, hash_val);
+    match verify_result {
+        HashError::Ok => {
+            println!("{}: OK", shown_name);
+        },
+        HashError::HashDifferent | HashError::HashVerifyFail(_) => {
+            println!("{}: FAILED!!!", shown_name);
+        },
+        _ => {
+            println!("{}: {}", shown_name, verify_result.message());
+        }  
+    }   
+
+    return error_status(&verify_result);
+}
+
+// Reports a line of reference data which could not be used and returns the exit status
+fn report_ref_error(e: &RefFileError) -> i32 {
+    eprintln!("{}", e.message());
+
+    return match e {
+        RefFileError::Malformed(_, _) => PROG_RETURN_MALFORMED,
+        RefFileError::ReadError(_) => PROG_RETURN_UNREADABLE
+    };
+}
+
+// Reports an entry for stdin in reference data which is itself read from stdin and returns
+// the exit status
+fn report_stdin_entry() -> i32 {
+    println!("{}: Data to verify can not be read from stdin when reference data is read from stdin", hasher::STDIN_NAME);
+
+    return PROG_RETURN_USAGE;
+}
+
+// Returns the exit status and the number of malformed lines
+fn verify_ref_file<R : Read>(ref_file: RefFile<R>, hasher: &mut dyn FileHash, ref_from_stdin: bool) -> (i32, u32) {
+    let mut status = PROG_RETURN_OK;
+    let mut malformed: u32 = 0;
+
+    for entry in &ref_file {
+        let entry_status = match entry {
+            Ok(i) if ref_from_stdin && (i.file_name == Path::new(hasher::STDIN_NAME)) => report_stdin_entry(),
+            Ok(i) => process_one_file(hasher, (&i.file_name, &i.hash, &i.region)),
+            Err(e) => report_ref_error(&e)
+        };
+
+        if entry_status == PROG_RETURN_MALFORMED {
+            malformed += 1;
+        }
+
+        status = worse_status(status, entry_status);
+    }
+
+    return (status, malformed);
+}
+
+// Returns the exit status
+pub fn process_one_chunked_file(hasher: &mut dyn FileHash, entry: &chunked::ChunkedEntry) -> i32 {
+    if entry.chunk_hashes.is_empty() {
+        return process_one_file(hasher, (&entry.file_name, &entry.hash, &entry.region));
+    }
+
+    let shown_name = display_name(&entry.file_name);
+
+    let (hash, chunk_hashes) = match hasher.hash_file_chunked(&entry.file_name, entry.chunk_size) {
+        Ok(val) => val,
+        Err(e) => {
+            println!("{}: {}", shown_name, e.message());
+            return error_status(&e);
+        }
+    };
+
+    match hasher::compare_hashes(&hasher.get_algo(), &entry.hash, &hash) {
+        HashError::Ok => {
+            println!("{}: OK", shown_name);
+            return PROG_RETURN_OK;
+        },
+        HashError::HashDifferent => {},
+        e => {
+            println!("{}: {}", shown_name, e.message());
+            return error_status(&e);
+        }
+    }
+
+    println!("{}: FAILED!!!", shown_name);
+
+    let file_len = match std::fs::metadata(&entry.file_name) {
+        Ok(m) => m.len(),
+        Err(_) => return PROG_RETURN_MISMATCH
+    };
+
+    for problem in chunked::find_bad_ranges(entry.chunk_size, file_len, &entry.chunk_hashes, &chunk_hashes) {
+        println!("{}: {}", shown_name, problem.message());
+    }
+
+    return PROG_RETURN_MISMATCH;
+}
+
+fn verify_chunked_ref_file<R : Read>(ref_file: ChunkedRefFile<R>, hasher: &mut dyn FileHash, ref_from_stdin: bool) -> (i32, u32) {
+    let mut status = PROG_RETURN_OK;
+    let mut malformed: u32 = 0;
+
+    for entry in ref_file {
+        let entry_status = match entry {
+            Ok(i) if ref_from_stdin && (i.file_name == Path::new(hasher::STDIN_NAME)) => report_stdin_entry(),
+            Ok(i) => process_one_chunked_file(hasher, &i),
+            Err(e) => report_ref_error(&e)
+        };
+
+        if entry_status == PROG_RETURN_MALFORMED {
+            malformed += 1;
+        }
+
+        status = worse_status(status, entry_status);
+    }
+
+    return (status, malformed);
+}
+
+// Reference data compressed with gzip, zstd or xz is decompressed. If reference data is read
+// from stdin, no entry can refer to the data read from stdin.
+fn verify_input<R : Read>(input: R, f: &Rc<dyn HashLineFormatter>, hasher: &mut dyn FileHash, use_chunks: bool, ref_from_stdin: bool) -> (i32, u32) {
+    let input = match compress::decompress(io::BufReader::new(input)) {
+        Ok(i) => i,
+        Err(e) => {
+            eprintln!("Unable to read reference data: {}", e);
+            return (PROG_RETURN_UNREADABLE, 0);
+        }
+    };
+
+    if use_chunks {
+        return verify_chunked_ref_file(ChunkedRefFile::new(input, f), hasher, ref_from_stdin);
+    }
+
+    return verify_ref_file(RefFile::new(input, f), hasher, ref_from_stdin);
+}
+
+fn make_formatter(algo_name: &str, use_bsd: bool) -> Rc<dyn HashLineFormatter> {
+    if use_bsd {
+        return Rc::new(formatter::BsdFormatter::new(algo_name));
+    } else {
+        return Rc::new(formatter::SimpleFormatter::new());
+    }
+}
+
+fn make_digest(algo: &str) -> (&'static str, Box<dyn DynDigest>) {
+    return match algo {
+        ALGO_ARG_SHA1 => (ALGO_SHA1, Box::new(sha1::Sha1::new())),
+        ALGO_ARG_SHA384 => (ALGO_SHA384, Box::new(Sha384::new())),
+        ALGO_ARG_SHA512 => (ALGO_SHA512, Box::new(Sha512::new())),
+        ALGO_ARG_BLAKE3 => (ALGO_BLAKE3, Box::new(blake3::Hasher::new())),
+        _ => (ALGO_SHA256, Box::new(Sha256::new()))
+    };
+}
+
+// Returns the SRI names of the algorithms and the lengths of their hashes in hex
+fn get_sri_algos(algos: &[String]) -> Result<Vec<(String, usize)>, String> {
+    let mut res = Vec::new();
+
+    for a in algos {
+        if !sri::SRI_ALGOS.contains(&a.as_str()) {
+            return Err(format!("Algorithm {} can not be used with SRI", a));
+        }
+
+        let (_, digest) = make_digest(a);
+        res.push((a.clone(), digest.output_size() * 2));
+    }
+
+    return Ok(res);
+}
+
+// Returns the algorithms given by --algo or --sha512
+fn get_algo_args(matches: &clap::ArgMatches) -> Vec<String> {
+    if let Ok(Some(algos)) = matches.try_get_many::<String>(ARG_ALGO) {
+        if is_option_present(matches, ARG_ALGO) {
+            return algos.cloned().collect();
+        }
+    }
+
+    if is_option_present(matches, ARG_SHA_512) {
+        return vec![String::from(ALGO_ARG_SHA512)];
+    }
+
+    return vec![String::from(ALGO_ARG_SHA256)];
+}
+
+// Git uses SHA1 unless a repository uses the SHA256 object format
+fn get_git_algo_args(matches: &clap::ArgMatches) -> Vec<String> {
+    if is_option_present(matches, ARG_ALGO) {
+        return get_algo_args(matches);
+    }
+
+    return vec![String::from(ALGO_ARG_SHA1)];
+}
+
+fn make_git_hash(algos: &[String]) -> Result<Hasher, String> {
+    if (algos.len() != 1) || ![ALGO_ARG_SHA1, ALGO_ARG_SHA256].contains(&algos[0].as_str()) {
+        return Err(String::from("Git objects can only be hashed with SHA1 or SHA256"));
+    }
+
+    let (algo_name, hash) = make_digest(&algos[0]);
+
+    return Ok(Hasher::new_git(algo_name, hash));
+}
+
+// Returns a hasher whose get_algo() returns algo if there is one
+fn make_hash_for_algo(algo: &str) -> Option<Box<dyn FileHash>> {
+    let algo_arg = |name: &str| [ALGO_ARG_SHA1, ALGO_ARG_SHA256, ALGO_ARG_SHA384, ALGO_ARG_SHA512, ALGO_ARG_BLAKE3]
+        .into_iter()
+        .find(|a| make_digest(a).0 == name)
+        .map(String::from);
+
+    if let Some(name) = algo.strip_prefix("GIT-") {
+        return make_git_hash(&[algo_arg(name)?]).ok().map(|h| Box::new(h) as Box<dyn FileHash>);
+    }
+
+    if let Some((name, leaf_size)) = algo.split_once("-TREE-") {
+        let leaf_size: u64 = leaf_size.parse().ok().filter(|s| *s > 0)?;
+        return Some(make_file_hash(&[algo_arg(name)?], Some(leaf_size)));
+    }
+
+    return Some(make_file_hash(&[algo_arg(algo)?], None));
+}
+
+// Several algorithms are calculated in one pass and the hashes are concatenated
+fn make_file_hash(algos: &[String], tree_leaf_size: Option<u64>) -> Box<dyn FileHash> {
+    if algos.len() > 1 {
+        let (names, digests): (Vec<&str>, Vec<Box<dyn DynDigest>>) = algos.iter().map(|a| make_digest(a)).unzip();
+        return Box::new(Hasher::new(&names.join("+"), Box::new(MultiDigest::new(digests))));
+    }
+
+    let (algo_name, hash) = make_digest(&algos[0]);
+
+    return match tree_leaf_size {
+        Some(leaf_size) => Box::new(Hasher::new_tree(algo_name, hash, leaf_size)),
+        None => Box::new(Hasher::new(algo_name, hash))
+    };
+}
+
+// Returns the value of a size option like --chunk-size or None if it is not present
+fn get_size_arg(matches: &clap::ArgMatches, id: &str) -> Result<Option<u64>, String> {
+    if !is_option_present(matches, id) {
+        return Ok(None);
+    }
+
+    let size_str: &String = matches.get_one(id).unwrap();
+
+    return match chunked::parse_size(size_str) {
+        Some(size) => Ok(Some(size)),
+        None => Err(format!("Invalid size '{}'", size_str))
+    };
+}
+
+fn is_option_present(matches: &clap::ArgMatches, id: &str) -> bool {
+    let test_res = matches.value_source(id);
+
+    return match test_res {
+        Some(v) => {
+            return v == clap::parser::ValueSource::CommandLine;
+        }
+        None => false
+    }
+}
+
+fn get_region_arg(matches: &clap::ArgMatches) -> Result<Region, String> {
+    let offset: u64 = *matches.get_one(ARG_OFFSET).unwrap_or(&0);
+    let length: Option<u64> = matches.get_one(ARG_LENGTH).copied();
+    let mut excludes: Vec<(u64, u64)> = Vec::new();
+
+    if let Some(ranges) = matches.get_many::<String>(ARG_EXCLUDE_RANGE) {
+        for r in ranges {
+            match region::parse_exclude(r) {
+                Some(e) => excludes.push(e),
+                None => return Err(format!("Invalid range '{}', expected <offset>+<length>", r))
+            }
+        }
+    }
+
+    return Ok(Region::new(offset, length, excludes));
+}
+
+// Creates one output for each algorithm. Without --output-dir the reference data is written
+// to stdout, otherwise to a file <algorithm>SUMS in that directory for each algorithm.
+// sri_algos contains the SRI names of the algorithms if SRI integrity strings are written.
+fn make_outputs(gen_matches: &clap::ArgMatches, algos: &[String], hasher_algo: &str, sri_algos: Option<Vec<(String, usize)>>) -> Result<Vec<ManifestOutput>, String> {
+    let encoding = encoding::Encoding::from_name(gen_matches.get_one::<String>(ARG_ENCODING).unwrap()).unwrap();
+    let mut res: Vec<ManifestOutput> = Vec::new();
+
+    // The hashes of all algorithms are written to one SRI integrity string
+    if let Some(sri_algos) = sri_algos {
+        let hex_len = sri_algos.iter().map(|(_, l)| l).sum();
+        let f: Rc<dyn HashLineFormatter> = Rc::new(sri::SriFormatter::new(sri_algos));
+
+        res.push(ManifestOutput { formatter: f, hash_part: 0..hex_len, writer: Box::new(io::stdout()) });
+
+        return Ok(res);
+    }
+
+    let mut pos: usize = 0;
+
+    for a in algos {
+        let (algo_name, digest) = make_digest(a);
+        let hex_len = digest.output_size() * 2;
+        // Only a single algorithm can be used in tree mode
+        let formatter_algo = if algos.len() == 1 { hasher_algo } else { algo_name };
+        let mut f = make_formatter(formatter_algo, is_option_present(gen_matches, ARG_USE_BSD));
+
+        if is_option_present(gen_matches, ARG_DIGEST) {
+            f = Rc::new(formatter::DigestFormatter::new(f, algo_name));
+        }
+
+        if encoding != encoding::Encoding::Hex {
+            f = Rc::new(formatter::EncodedFormatter::new(f, encoding));
+        }
+
+        let writer: Box<dyn Write> = match gen_matches.get_one::<PathBuf>(ARG_OUTPUT_DIR) {
+            Some(dir) => {
+                let file_name = dir.join(format!("{}SUMS", algo_name));
+                let out_file = File::create(&file_name).map_err(|e| format!("{}: {}", display_name(&file_name), e))?;
+                Box::new(io::BufWriter::new(out_file))
+            },
+            None => Box::new(io::stdout())
+        };
+
+        res.push(ManifestOutput { formatter: f, hash_part: pos..pos + hex_len, writer });
+        pos += hex_len;
+    }
+
+    return Ok(res);
+}
+
+fn gen_command(gen_matches: &clap::ArgMatches) -> i32 {
+    let (chunk_size, tree_leaf_size) = match (get_size_arg(gen_matches, ARG_CHUNK_SIZE), get_size_arg(gen_matches, ARG_TREE)) {
+        (Ok(c), Ok(t)) => (c, t),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return PROG_RETURN_USAGE;
+        }
+    };
+
+    if chunk_size.is_some() && tree_leaf_size.is_some() {
+        eprintln!("Chunk hashes can not be used in tree mode");
+        return PROG_RETURN_USAGE;
+    }
+
+    let region = match get_region_arg(gen_matches) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            return PROG_RETURN_USAGE;
+        }
+    };
+
+    if chunk_size.is_some() && !region.is_whole() {
+        eprintln!("Chunk hashes can only be calculated for whole files");
+        return PROG_RETURN_USAGE;
+    }
+
+    let use_git = is_option_present(gen_matches, ARG_GIT);
+    let algos = if use_git { get_git_algo_args(gen_matches) } else { get_algo_args(gen_matches) };
+
+    if (algos.len() > 1) && (chunk_size.is_some() || tree_leaf_size.is_some()) {
+        eprintln!("Chunk hashes and hash trees can only be used with one algorithm");
+        return PROG_RETURN_USAGE;
+    }
+
+    if (algos.len() > 1) && !is_option_present(gen_matches, ARG_OUTPUT_DIR) && !is_option_present(gen_matches, ARG_SRI) {
+        eprintln!("Several algorithms need --output-dir");
+        return PROG_RETURN_USAGE;
+    }
+
+    let use_archives = is_option_present(gen_matches, ARG_ARCHIVE);
+
+    if use_archives && (chunk_size.is_some() || !region.is_whole()) {
+        eprintln!("Chunk hashes and regions can not be used for archive members");
+        return PROG_RETURN_USAGE;
+    }
+
+    let use_xattrs = is_option_present(gen_matches, ARG_XATTR);
+
+    if use_xattrs && ((algos.len() > 1) || use_archives || chunk_size.is_some() || !region.is_whole()) {
+        eprintln!("Extended attributes can only store hashes of whole files calculated with one algorithm");
+        return PROG_RETURN_USAGE;
+    }
+
+    if use_xattrs && is_option_present(gen_matches, ARG_DECOMPRESS) {
+        eprintln!("Extended attributes can not store hashes of decompressed data");
+        return PROG_RETURN_USAGE;
+    }
+
+    let mut h = make_file_hash(&algos, tree_leaf_size);
+
+    if use_git {
+        h = match make_git_hash(&algos) {
+            Ok(g) => Box::new(g),
+            Err(e) => {
+                eprintln!("{}", e);
+                return PROG_RETURN_USAGE;
+            }
+        };
+    }
+
+    if use_xattrs {
+        h = Box::new(xattrs::XattrHasher::new(h));
+    }
+
+    if is_option_present(gen_matches, ARG_DECOMPRESS) {
+        h = Box::new(compress::DecompressHasher::new(h));
+    }
+
+    if use_archives {
+        h = Box::new(archive::ArchiveHasher::new(h));
+    }
+
+    let sri_algos = match is_option_present(gen_matches, ARG_SRI) {
+        true => match get_sri_algos(&algos) {
+            Ok(a) => Some(a),
+            Err(e) => {
+                eprintln!("{}", e);
+                return PROG_RETURN_USAGE;
+            }
+        },
+        false => None
+    };
+
+    let mut outputs = match make_outputs(gen_matches, &algos, &h.get_algo(), sri_algos) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            return PROG_RETURN_ERR;
+        }
+    };
+
+    let mut files_hashed: u32 = 0;
+    let mut status = PROG_RETURN_OK;
+    let fail_fast = is_option_present(gen_matches, ARG_FAIL_FAST);
+    
+    let in_files_match_data= gen_matches.get_many::<PathBuf>(ARG_FILES);
+
+    let list_name: Option<&PathBuf> = gen_matches.get_one(ARG_FILES_FROM);
+    let list_from_stdin = list_name.is_some_and(|n| n == Path::new(hasher::STDIN_NAME));
+
+    if list_from_stdin && is_option_present(gen_matches, ARG_FROM_STDIN) {
+        eprintln!("File names can not be read from stdin twice");
+        return PROG_RETURN_USAGE;
+    }
+
+    if (list_from_stdin || is_option_present(gen_matches, ARG_FROM_STDIN)) && in_files_match_data.clone().is_some_and(|mut x| x.any(|n| n == Path::new(hasher::STDIN_NAME))) {
+        eprintln!("Data to hash can not be read from stdin when file names are read from stdin");
+        return PROG_RETURN_USAGE;
+    }
+
+    if let Some(in_files) = in_files_match_data.clone() {
+        let mut file_names: Vec<PathBuf> = Vec::new();
+        in_files.for_each(|x| file_names.push(x.clone()));
+        let (hash_count, hash_status) = hash_files(file_names, h.as_mut(), &mut outputs, chunk_size, &region, fail_fast);
+        files_hashed += hash_count;
+        status = worse_status(status, hash_status);
+    }
+
+    let separator = if is_option_present(gen_matches, ARG_NULL) { b'\0' } else { b'\n' };
+
+    if fail_fast && (status != PROG_RETURN_OK) {
+        return status;
+    }
+
+    if is_option_present(gen_matches, ARG_FROM_STDIN) {
+        let (hash_count, hash_status) = hash_listed_files(io::stdin().lock(), separator, true, h.as_mut(), &mut outputs, chunk_size, &region, fail_fast);
+        files_hashed += hash_count;
+        status = worse_status(status, hash_status);
+    }
+
+    if fail_fast && (status != PROG_RETURN_OK) {
+        return status;
+    }
+
+    if list_from_stdin {
+        let (hash_count, hash_status) = hash_listed_files(io::stdin().lock(), separator, true, h.as_mut(), &mut outputs, chunk_size, &region, fail_fast);
+        files_hashed += hash_count;
+        status = worse_status(status, hash_status);
+    } else if let Some(list_name) = list_name {
+        match File::open(list_name) {
+            Ok(list_file) => {
+                let (hash_count, hash_status) = hash_listed_files(io::BufReader::new(list_file), separator, false, h.as_mut(), &mut outputs, chunk_size, &region, fail_fast);
+                files_hashed += hash_count;
+                status = worse_status(status, hash_status);
+            },
+            Err(e) => {
+                eprintln!("{}: {}", display_name(list_name), e);
+                return io_status(&e);
+            }
+        }
+    }
+
+    for o in outputs.iter_mut() {
+        if let Err(e) = o.writer.flush() {
+            eprintln!("Unable to write reference data: {}", e);
+            status = worse_status(status, PROG_RETURN_ERR);
+        }
+    }
+
+    if (files_hashed == 0) && (status == PROG_RETURN_OK) {
+        eprintln!("No input specified");
+        return PROG_RETURN_USAGE;
+    }
+
+    return status;
+}
+
+fn read_file_arg(matches: &clap::ArgMatches, id: &str) -> Result<Vec<u8>, String> {
+    let file_name: &PathBuf = matches.get_one(id).unwrap();
+
+    return match std::fs::read(file_name) {
+        Ok(data) => Ok(data),
+        Err(e) => Err(format!("{}: {}", display_name(file_name), e))
+    };
+}
+
+// Checks the signature over the reference data and returns the verified reference data.
+// If no input file is given the reference data has to be embedded in the signature.
+fn verify_signed_input(verify_matches: &clap::ArgMatches) -> Result<Vec<u8>, String> {
+    if !is_option_present(verify_matches, ARG_PUB_KEY) {
+        return Err(String::from("A public key is needed to check a signature"));
+    }
+
+    let sig_type: &String = verify_matches.get_one(ARG_SIG_TYPE).unwrap();
+    let key_file: &PathBuf = verify_matches.get_one(ARG_PUB_KEY).unwrap();
+    let sig_data = read_file_arg(verify_matches, ARG_SIGNATURE)?;
+
+    let message = match is_option_present(verify_matches, ARG_INPUT_FILE) {
+        true => Some(read_file_arg(verify_matches, ARG_INPUT_FILE)?),
+        false => None
+    };
+
+    let verifier = signature::make_verifier(sig_type, key_file).map_err(|e| e.message())?;
+
+    return verifier.verify(&sig_data, message).map_err(|e| e.message());
+}
+
+// Verifies files against the hashes stored in their extended attributes
+fn verify_xattr_files(file_names: &[PathBuf]) -> i32 {
+    let mut status = PROG_RETURN_OK;
+
+    for file_name in file_names {
+        let shown_name = display_name(file_name);
+        let (verify_result, modified) = xattrs::verify_stored_hash(&mut make_hash_for_algo, file_name);
+
+        match (&verify_result, modified) {
+            (HashError::Ok, false) => println!("{}: OK", shown_name),
+            (HashError::Ok, true) => println!("{}: OK (modified after hashing)", shown_name),
+            (HashError::HashVerifyFail(_), true) => println!("{}: FAILED!!! (modified after hashing)", shown_name),
+            (HashError::HashVerifyFail(_), false) => println!("{}: FAILED!!! (not modified after hashing)", shown_name),
+            _ => println!("{}: {}", shown_name, verify_result.message())
+        }
+
+        status = worse_status(status, error_status(&verify_result));
+    }
+
+    if status != PROG_RETURN_OK {
+        eprintln!("There were errors!!");
+    }
+
+    return status;
+}
+
+fn verify_command(verify_matches: &clap::ArgMatches) -> i32 {
+    let tree_leaf_size = match get_size_arg(verify_matches, ARG_TREE) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("{}", e);
+            return PROG_RETURN_USAGE;
+        }
+    };
+
+    // The stored hashes tell which algorithm is used
+    if is_option_present(verify_matches, ARG_XATTR) {
+        let file_names: Vec<PathBuf> = verify_matches.get_many::<PathBuf>(ARG_FILES).unwrap().cloned().collect();
+
+        return verify_xattr_files(&file_names);
+    }
+
+    if ![ARG_SIGNATURE, ARG_INPUT_FILE, ARG_FROM_STDIN].iter().any(|a| is_option_present(verify_matches, a)) {
+        eprintln!("No reference data given. Use --input, --from-stdin or --signature");
+        return PROG_RETURN_USAGE;
+    }
+
+    let use_git = is_option_present(verify_matches, ARG_GIT);
+    let algos = if use_git { get_git_algo_args(verify_matches) } else { get_algo_args(verify_matches) };
+    let mut h = make_file_hash(&algos, tree_leaf_size);
+
+    if use_git {
+        h = match make_git_hash(&algos) {
+            Ok(g) => Box::new(g),
+            Err(e) => {
+                eprintln!("{}", e);
+                return PROG_RETURN_USAGE;
+            }
+        };
+    }
+
+    if is_option_present(verify_matches, ARG_DECOMPRESS) {
+        h = Box::new(compress::DecompressHasher::new(h));
+    }
+
+    if is_option_present(verify_matches, ARG_ARCHIVE) {
+        h = Box::new(archive::ArchiveHasher::new(h));
+    }
+
+
+    let f = match is_option_present(verify_matches, ARG_SRI) {
+        true => match get_sri_algos(&algos) {
+            Ok(sri_algos) => Rc::new(sri::SriFormatter::new(sri_algos)),
+            Err(e) => {
+                eprintln!("{}", e);
+                return PROG_RETURN_USAGE;
+            }
+        },
+        false => make_formatter(&h.get_algo(), is_option_present(verify_matches, ARG_USE_BSD))
+    };
+    let use_chunks = is_option_present(verify_matches, ARG_CHUNKED);
+    let ref_from_stdin = is_option_present(verify_matches, ARG_FROM_STDIN);
+    let mut status = PROG_RETURN_OK;
+    let mut malformed: u32 = 0;
+    let mut add_result = |(input_status, bad_lines): (i32, u32)| {
+        status = worse_status(status, input_status);
+        malformed += bad_lines;
+    };
+
+    if is_option_present(verify_matches, ARG_SIGNATURE) {
+        let message = match verify_signed_input(verify_matches) {
+            Ok(m) => m,
+            Err(e) => {
+                eprintln!("{}", e);
+                return PROG_RETURN_ERR;
+            }
+        };
+
+        add_result(verify_input(message.as_slice(), &f, h.as_mut(), use_chunks, ref_from_stdin));
+    } else if is_option_present(verify_matches, ARG_INPUT_FILE) {
+        let in_file: Option<&PathBuf> = verify_matches.get_one(ARG_INPUT_FILE);
+        let ref_file: PathBuf = in_file.unwrap().clone();
+
+        let stream_in = match File::open(&ref_file) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("{}: {}", display_name(&ref_file), e);
+                return io_status(&e);
+            }
+        };
+
+        add_result(verify_input(stream_in, &f, h.as_mut(), use_chunks, ref_from_stdin));
+    }
+
+    if is_option_present(verify_matches, ARG_FROM_STDIN) {
+        add_result(verify_input(io::stdin(), &f, h.as_mut(), use_chunks, ref_from_stdin));
+    }
+
+    if malformed > 0 {
+        eprintln!("{} line(s) of the reference data are malformed", malformed);
+    }
+
+    if status != PROG_RETURN_OK {
+        eprintln!("There were errors!!");
+    } 
+
+    return status;
+}
+
+// Writes a message about a file which does not match the reference data to stdout and
+// to the log file
+#[cfg(target_os = "linux")]
+fn report_violation(file_name: &Path, res: &HashError, log: &mut Option<File>) {
+    let line = match res {
+        HashError::HashDifferent | HashError::HashVerifyFail(_) => format!("{}: FAILED!!!", display_name(file_name)),
+        _ => format!("{}: {}", display_name(file_name), res.message())
+    };
+
+    println!("{}", line);
+
+    if let Some(log_file) = log {
+        if let Err(e) = writeln!(log_file, "{}", line) {
+            eprintln!("Unable to write log file: {}", e);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn watch_command(watch_matches: &clap::ArgMatches) -> i32 {
+    let mut h = make_file_hash(&get_algo_args(watch_matches), None);
+    let f = make_formatter(&h.get_algo(), is_option_present(watch_matches, ARG_USE_BSD));
+    let ref_file: &PathBuf = watch_matches.get_one(ARG_INPUT_FILE).unwrap();
+
+    let input = match File::open(ref_file).and_then(|r| compress::decompress(io::BufReader::new(r))) {
+        Ok(i) => i,
+        Err(e) => {
+            eprintln!("{}: {}", display_name(ref_file), e);
+            return io_status(&e);
+        }
+    };
+
+    let mut log = match watch_matches.get_one::<PathBuf>(ARG_LOG) {
+        Some(log_name) => match std::fs::OpenOptions::new().create(true).append(true).open(log_name) {
+            Ok(log_file) => Some(log_file),
+            Err(e) => {
+                eprintln!("{}: {}", display_name(log_name), e);
+                return io_status(&e);
+            }
+        },
+        None => None
+    };
+
+    let mut watcher = match watch::Watcher::new(h.as_mut()) {
+        Ok(w) => w,
+        Err(e) => {
+            eprintln!("Unable to watch files: {}", e);
+            return PROG_RETURN_ERR;
+        }
+    };
 
-fn hash_files<T>(file_names: T, h: &mut dyn FileHash, line_formatter: &dyn HashLineFormatter) -> (u32, bool)
-where 
-    T: IntoIterator<Item=String>
-{
-    let mut count: u32 = 0;
+    let mut status = PROG_RETURN_OK;
+    let mut files_watched: u32 = 0;
 
-    for i in file_names {
-        let hash = match h.hash_file(&i) {
-            Ok(val) => val,
-            Err(err) => {
-                eprintln!("{}", err.message()); 
-                return (count, false);
+    // Files are verified once before they are watched
+    for entry in &RefFile::new(input, &f) {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                status = worse_status(status, report_ref_error(&e));
+                continue;
             }
         };
 
-        println!("{}", line_formatter.format(&hash, &i));
-        count += 1;
+        let res = watcher.verify(&entry);
+        let file_name = entry.file_name.clone();
+
+        match watcher.add(entry) {
+            Ok(_) => files_watched += 1,
+            Err(e) => {
+                eprintln!("{}: {}", display_name(&file_name), e.message());
+                status = worse_status(status, error_status(&e));
+                continue;
+            }
+        }
+
+        if !matches!(res, HashError::Ok) {
+            report_violation(&file_name, &res, &mut log);
+        }
+    }
+
+    if files_watched == 0 {
+        eprintln!("No files to watch");
+        return worse_status(status, PROG_RETURN_ERR);
     }
 
-    return (count, true);
+    loop {
+        match watcher.wait() {
+            Ok(results) => {
+                for (file_name, res) in results {
+                    if !matches!(res, HashError::Ok) {
+                        report_violation(&file_name, &res, &mut log);
+                    }
+                }
+            },
+            Err(e) => {
+                eprintln!("Unable to watch files: {}", e);
+                return PROG_RETURN_ERR;
+            }
+        }
+    }
 }
 
-pub fn process_one_file(hasher: &mut dyn FileHash, ref_data: (&String, &String)) -> bool {
-    let (file_name, hash_val) = ref_data;
+// Time after which scrub writes its state file again
+const SCRUB_CHECKPOINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
 
-    let verify_result = hasher.verify_file(file_name, hash_val);
-    match verify_result {
-        HashError::Ok => {
-            println!("{}: OK", file_name);
-            return true;
-        },
-        HashError::HashDifferent | HashError::HashVerifyFail(_) => {
-            println!("{}: FAILED!!!", file_name);
-            return false;
+fn scrub_command(scrub_matches: &clap::ArgMatches) -> i32 {
+    let budget = scrub::Budget {
+        time: match scrub_matches.get_one::<String>(ARG_TIME_BUDGET) {
+            Some(t) => match scrub::parse_duration(t) {
+                Some(d) => Some(d),
+                None => {
+                    eprintln!("Invalid duration '{}'", t);
+                    return PROG_RETURN_USAGE;
+                }
+            },
+            None => None
         },
-        _ => {
-            println!("{}: {}", file_name, verify_result.message());
-            return false;
-        }  
-    }   
-}
+        bytes: match get_size_arg(scrub_matches, ARG_BYTE_BUDGET) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("{}", e);
+                return PROG_RETURN_USAGE;
+            }
+        }
+    };
 
-fn verify_ref_file<R : Read>(ref_file: RefFile<R>, hasher: &mut dyn FileHash) -> bool {
-    let mut all_ok = true;
- 
-    ref_file.into_iter().for_each(|i| all_ok &= process_one_file(hasher, (&i.0, &i.1)));
+    let mut h = make_file_hash(&get_algo_args(scrub_matches), None);
+    let f = make_formatter(&h.get_algo(), is_option_present(scrub_matches, ARG_USE_BSD));
+    let ref_file: &PathBuf = scrub_matches.get_one(ARG_INPUT_FILE).unwrap();
+    let state_file: &PathBuf = scrub_matches.get_one(ARG_STATE).unwrap();
 
-    return all_ok;   
-}
+    let input = match File::open(ref_file).and_then(|r| compress::decompress(io::BufReader::new(r))) {
+        Ok(i) => i,
+        Err(e) => {
+            eprintln!("{}: {}", display_name(ref_file), e);
+            return io_status(&e);
+        }
+    };
 
-fn make_formatter(algo_name: &String, use_bsd: bool) -> Rc<dyn HashLineFormatter> {
-    if use_bsd {
-        return Rc::new(formatter::BsdFormatter::new(algo_name));
-    } else {
-        return Rc::new(formatter::SimpleFormatter::new());
+    let load_result = match File::open(state_file) {
+        Ok(state_in) => scrub::ScrubState::load(io::BufReader::new(state_in)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(scrub::ScrubState::new()),
+        Err(e) => Err(e)
+    };
+
+    let mut state = match load_result {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}: {}", display_name(state_file), e);
+            return PROG_RETURN_ERR;
+        }
+    };
+
+    let mut status = PROG_RETURN_OK;
+    let mut entries: Vec<reffile::RefEntry> = Vec::new();
+
+    for entry in &RefFile::new(input, &f) {
+        match entry {
+            Ok(e) => entries.push(e),
+            Err(e) => status = worse_status(status, report_ref_error(&e))
+        }
     }
-}
 
-fn make_file_hash(use_sha_512: bool) -> Box<dyn FileHash> {
-    let mut hash: Box<dyn DynDigest> = Box::new(Sha256::new());
-    let mut algo_name = ALGO_SHA256;
+    let file_names: Vec<PathBuf> = entries.iter().map(|e| e.file_name.clone()).collect();
+    state.order(&mut entries);
+
+    let start = std::time::Instant::now();
+    let mut last_save = start;
+    let mut bytes_read: u64 = 0;
+    let mut files_verified: usize = 0;
+    let mut save_status = PROG_RETURN_OK;
+
+    for entry in &entries {
+        if budget.is_used(start.elapsed(), bytes_read) {
+            break;
+        }
+
+        bytes_read += std::fs::metadata(&entry.file_name).map(|m| m.len()).unwrap_or(0);
+        let file_status = process_one_file(h.as_mut(), (&entry.file_name, &entry.hash, &entry.region));
+        status = worse_status(status, file_status);
+        files_verified += 1;
+
+        // Files which could not be read have to be verified again by the next run
+        if (file_status == PROG_RETURN_OK) || (file_status == PROG_RETURN_MISMATCH) {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            state.mark_checked(&entry.file_name, now);
+        }
+
+        // An interrupted scrub loses at most the progress since the last checkpoint
+        if (save_status == PROG_RETURN_OK) && (last_save.elapsed() >= SCRUB_CHECKPOINT_INTERVAL) {
+            if let Err(e) = state.save_file(state_file, &file_names) {
+                eprintln!("{}: {}", display_name(state_file), e);
+                save_status = PROG_RETURN_ERR;
+            }
+
+            last_save = std::time::Instant::now();
+        }
+    }
+
+    if save_status == PROG_RETURN_OK {
+        if let Err(e) = state.save_file(state_file, &file_names) {
+            eprintln!("{}: {}", display_name(state_file), e);
+            save_status = PROG_RETURN_ERR;
+        }
+    }
+
+    status = worse_status(status, save_status);
+
+    eprintln!("Verified {} of {} files ({} bytes)", files_verified, entries.len(), bytes_read);
 
-    if use_sha_512 {
-        hash = Box::new(Sha512::new());
-        algo_name = ALGO_SHA512;
+    if status != PROG_RETURN_OK {
+        eprintln!("There were errors!!");
     }
 
-    return Box::new(Hasher::new(algo_name, hash));
+    return status;
 }
 
-fn is_option_present(matches: &clap::ArgMatches, id: &str) -> bool {
-    let test_res = matches.value_source(id);
+// Checks the integrity attributes of the scripts and stylesheets referenced by HTML files
+// against the local files
+fn check_html_command(html_matches: &clap::ArgMatches) -> i32 {
+    let mut status = PROG_RETURN_OK;
 
-    return match test_res {
-        Some(v) => {
-            return v == clap::parser::ValueSource::CommandLine;
+    for html_name in html_matches.get_many::<PathBuf>(ARG_FILES).unwrap() {
+        let html = match std::fs::read(html_name) {
+            Ok(data) => String::from_utf8_lossy(&data).to_string(),
+            Err(e) => {
+                eprintln!("{}: {}", display_name(html_name), e);
+                status = worse_status(status, io_status(&e));
+                continue;
+            }
+        };
+
+        let html_dir = html_name.parent().unwrap_or(Path::new(""));
+        let root: &Path = html_matches.get_one::<PathBuf>(ARG_ROOT).map_or(html_dir, |r| r.as_path());
+
+        for resource in sri::find_resources(&html) {
+            let shown_name = format!("{}: {}", display_name(html_name), resource.url);
+
+            let file_name = match sri::local_path(&resource.url, html_dir, root) {
+                Some(f) => f,
+                None => {
+                    println!("{}: SKIPPED (not a local file in the root directory)", shown_name);
+                    continue;
+                }
+            };
+
+            let tokens = sri::strongest_tokens(&sri::parse_integrity(&resource.integrity));
+
+            if tokens.is_empty() {
+                println!("{}: Integrity attribute '{}' contains no supported hash", shown_name, resource.integrity);
+                status = worse_status(status, PROG_RETURN_MALFORMED);
+                continue;
+            }
+
+            let mut h = make_file_hash(&[tokens[0].algo.clone()], None);
+
+            let hash_res = match h.hash_file(&file_name) {
+                // The file has to match one of the tokens
+                Ok(hash) => {
+                    let mut results = tokens.iter().map(|t| hasher::compare_hashes(&h.get_algo(), &t.hash, &hash));
+                    let first = results.next().unwrap();
+
+                    if results.any(|r| matches!(r, HashError::Ok)) { HashError::Ok } else { first }
+                },
+                Err(e) => e
+            };
+
+            match hash_res {
+                HashError::Ok => println!("{}: OK", shown_name),
+                HashError::HashDifferent => println!("{}: FAILED!!!", shown_name),
+                _ => println!("{}: {}", shown_name, hash_res.message())
+            }
+
+            status = worse_status(status, error_status(&hash_res));
         }
-        None => false
     }
+
+    if status != PROG_RETURN_OK {
+        eprintln!("There were errors!!");
+    }
+
+    return status;
 }
 
-fn gen_command(gen_matches: &clap::ArgMatches) -> i32 {
-    let mut h = make_file_hash(is_option_present(gen_matches, ARG_SHA_512));
-    let f = make_formatter(&h.get_algo(), is_option_present(gen_matches, ARG_USE_BSD));
-    let mut files_hashed: u32 = 0;
-    let mut all_ok = true;
-    
-    let in_files_match_data= gen_matches.get_many::<String>(ARG_FILES);
+fn verify_oci_command(oci_matches: &clap::ArgMatches) -> i32 {
+    let layout: &PathBuf = oci_matches.get_one(ARG_LAYOUT).unwrap();
+    let mut hasher_for = |algo: &str| -> Option<Box<dyn FileHash>> {
+        return match algo {
+            ALGO_ARG_SHA256 | ALGO_ARG_SHA384 | ALGO_ARG_SHA512 | ALGO_ARG_BLAKE3 => Some(make_file_hash(&[algo.to_string()], None)),
+            _ => None
+        };
+    };
 
-    if let Some(in_files) = in_files_match_data {
-        let mut file_names: Vec<String> = Vec::new();
-        in_files.for_each(|x| file_names.push(String::from(x)));
-        let (hash_count, ok) = hash_files(file_names, h.as_mut(), f.as_ref());
-        files_hashed += hash_count;
-        all_ok &= ok;
-    }
+    let mut status = PROG_RETURN_OK;
 
-    if is_option_present(gen_matches, ARG_FROM_STDIN) {
-        let mut line_iter = io::BufReader::new(io::stdin()).lines().map(|x| x.unwrap());
-        let (hash_count, ok) = hash_files(&mut line_iter, h.as_mut(), f.as_ref());
-        files_hashed += hash_count;
-        all_ok &= ok;
+    for report in oci::LayoutChecker::new(layout, &mut hasher_for).check() {
+        let report_status = match &report {
+            oci::OciReport::BlobOk(_) | oci::OciReport::Unreferenced(_) => PROG_RETURN_OK,
+            oci::OciReport::BlobFailed(_, e) => error_status(e),
+            oci::OciReport::Unsupported(_) => PROG_RETURN_UNREADABLE,
+            oci::OciReport::Missing(_, _) => PROG_RETURN_MISSING,
+            oci::OciReport::WrongSize(_, _, _) => PROG_RETURN_MISMATCH,
+            oci::OciReport::Malformed(_, _) => PROG_RETURN_MALFORMED
+        };
+
+        println!("{}", report.message());
+        status = worse_status(status, report_status);
     }
 
-    if files_hashed == 0 {
-        eprintln!("No input specified");
-        return PROG_RETURN_ERR;
+    if status != PROG_RETURN_OK {
+        eprintln!("There were errors!!");
     }
 
-    if !all_ok {
-        return PROG_RETURN_ERR;
+    return status;
+}
+
+fn proof_command(proof_matches: &clap::ArgMatches) -> i32 {
+    let leaf_size = match get_size_arg(proof_matches, ARG_TREE) {
+        Ok(t) => t.unwrap(),
+        Err(e) => {
+            eprintln!("{}", e);
+            return PROG_RETURN_USAGE;
+        }
+    };
+
+    let file_name: &PathBuf = proof_matches.get_one(ARG_FILE).unwrap();
+    let leaf_index: &usize = proof_matches.get_one(ARG_LEAF).unwrap();
+    let (algo_name, hash) = make_digest(&get_algo_args(proof_matches)[0]);
+    let mut h = Hasher::new(algo_name, hash);
+
+    let tree_res = match File::open(file_name) {
+        Ok(mut f) => h.hash_data_tree(&mut f, leaf_size).map_err(|e| e.in_file(file_name, 0)),
+        Err(e) => Err(HashError::FileOpenError(display_name(file_name), e))
+    };
+
+    let tree = match tree_res {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("{}", e.message());
+            return error_status(&e);
+        }
+    };
+
+    let proof = match tree.proof(*leaf_index) {
+        Some(p) => p,
+        None => {
+            eprintln!("File '{}' has only {} leaves", display_name(file_name), tree.leaf_count());
+            return PROG_RETURN_USAGE;
+        }
+    };
+
+    println!("root {}", hasher::to_hex_string(tree.root()));
+    println!("leaf {} {}", leaf_index, hasher::to_hex_string(tree.leaf(*leaf_index).unwrap()));
+
+    for step in proof {
+        match step {
+            merkle::ProofStep::Left(sibling) => println!("left {}", hasher::to_hex_string(&sibling)),
+            merkle::ProofStep::Right(sibling) => println!("right {}", hasher::to_hex_string(&sibling))
+        }
     }
 
     return PROG_RETURN_OK;
 }
 
-fn verify_command(verify_matches: &clap::ArgMatches) -> i32 {
-    let mut h = make_file_hash(is_option_present(verify_matches, ARG_SHA_512));
-    let f = make_formatter(&h.get_algo(), is_option_present(verify_matches, ARG_USE_BSD));
-    let mut all_ok = true;  
-    
-    if is_option_present(verify_matches, ARG_INPUT_FILE) {
-        let in_file: Option<&String> = verify_matches.get_one(ARG_INPUT_FILE);
-        let ref_file: String = String::from(in_file.unwrap().clone());
-
-        let stream_in = match File::open(ref_file) {
-            Ok(f) => f,
+fn tree_hash_command(tree_matches: &clap::ArgMatches) -> i32 {
+    let mut git_hasher = match is_option_present(tree_matches, ARG_GIT) {
+        true => match make_git_hash(&get_git_algo_args(tree_matches)) {
+            Ok(g) => Some(g),
             Err(e) => {
                 eprintln!("{}", e);
-                return PROG_RETURN_ERR;
+                return PROG_RETURN_USAGE;
             }
+        },
+        false => None
+    };
+
+    let mut h = make_file_hash(&get_algo_args(tree_matches), None);
+    let algo = git_hasher.as_ref().map_or(h.get_algo(), |g| g.get_algo());
+    let f = make_formatter(&algo, is_option_present(tree_matches, ARG_USE_BSD));
+    let include_modes = is_option_present(tree_matches, ARG_MODES);
+    let mut status = PROG_RETURN_OK;
+
+    for dir_name in tree_matches.get_many::<PathBuf>(ARG_DIRS).unwrap() {
+        let hash_res = match git_hasher.as_mut() {
+            Some(g) => dirhash::GitTreeHasher::new(g).hash_dir(dir_name),
+            None => dirhash::DirHasher::new(h.as_mut(), include_modes).hash_dir(dir_name)
         };
 
-        all_ok &= verify_ref_file(RefFile::new(stream_in, &f), h.as_mut());
+        match hash_res {
+            Ok(hash) => println!("{}", f.format(&hash, dir_name)),
+            Err(e) => {
+                eprintln!("{}", e.message());
+                status = worse_status(status, error_status(&e));
+            }
+        }
     }
 
-    if is_option_present(verify_matches, ARG_FROM_STDIN) {
-        all_ok &= verify_ref_file(RefFile::new(io::stdin(), &f), h.as_mut());
+    return status;
+}
+
+fn chunks_command(chunks_matches: &clap::ArgMatches) -> i32 {
+    let avg_size = match get_size_arg(chunks_matches, ARG_AVG_SIZE) {
+        Ok(size) => size.unwrap_or(cdc::DEFAULT_AVG_SIZE),
+        Err(e) => {
+            eprintln!("{}", e);
+            return PROG_RETURN_USAGE;
+        }
+    };
+
+    let chunker = match cdc::Chunker::new(avg_size) {
+        Some(c) => c,
+        None => {
+            eprintln!("The average chunk size has to be a power of two between 256 and 1G");
+            return PROG_RETURN_USAGE;
+        }
+    };
+
+    let mut h = make_file_hash(&get_algo_args(chunks_matches), None);
+    let mut stats = cdc::DedupStats::new();
+    let mut status = PROG_RETURN_OK;
+
+    for file_name in chunks_matches.get_many::<PathBuf>(ARG_FILES).unwrap() {
+        let shown_name = display_name(file_name);
+        let chunk_res = match File::open(file_name) {
+            Ok(mut f) => chunker.chunk_data(&mut f, h.as_mut()).map_err(|e| e.in_file(file_name, 0)),
+            Err(e) => Err(HashError::FileOpenError(shown_name.clone(), e))
+        };
+
+        let chunks = match chunk_res {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("{}", e.message());
+                status = worse_status(status, error_status(&e));
+                continue;
+            }
+        };
+
+        for chunk in chunks {
+            println!("{} {} {} {}", chunk.hash, chunk.offset, chunk.length, shown_name);
+            stats.add(&chunk);
+        }
     }
 
-    if !all_ok {
-        eprintln!("There were errors!!");
-        return PROG_RETURN_ERR;
-    } 
+    eprintln!("{}", stats.message());
 
-    return PROG_RETURN_OK;
+    return status;
 }
 
 const COMMAND_GEN: &str = "gen";
+const COMMAND_CHUNKS: &str = "chunks";
+const COMMAND_TREE_HASH: &str = "tree-hash";
+const COMMAND_PROOF: &str = "proof";
 const COMMAND_VERIFY: &str = "verify";
+#[cfg(target_os = "linux")]
+const COMMAND_WATCH: &str = "watch";
+const COMMAND_SCRUB: &str = "scrub";
+const COMMAND_CHECK_HTML: &str = "check-html";
+const COMMAND_VERIFY_OCI: &str = "verify-oci";
 const ARG_INPUT_FILE: &str = "inputfile";
 const ARG_SHA_512: &str = "sha512";
 const ARG_USE_BSD: &str = "use-bsd";
 const ARG_FROM_STDIN: &str = "from-stdin";
 const ARG_FILES: &str = "files";
+const ARG_SIGNATURE: &str = "signature";
+const ARG_PUB_KEY: &str = "pub-key";
+const ARG_SIG_TYPE: &str = "sig-type";
+const ARG_CHUNK_SIZE: &str = "chunk-size";
+const ARG_CHUNKED: &str = "chunked";
+const ARG_TREE: &str = "tree";
+const ARG_FILE: &str = "file";
+const ARG_LEAF: &str = "leaf";
+const ARG_DIRS: &str = "dirs";
+const ARG_MODES: &str = "modes";
+const ARG_AVG_SIZE: &str = "avg-size";
+const ARG_OFFSET: &str = "offset";
+const ARG_LENGTH: &str = "length";
+const ARG_EXCLUDE_RANGE: &str = "exclude-range";
+const ARG_NULL: &str = "null";
+const ARG_FILES_FROM: &str = "files-from";
+const ARG_FAIL_FAST: &str = "fail-fast";
+const ARG_ENCODING: &str = "encoding";
+const ARG_ALGO: &str = "algo";
+const ARG_OUTPUT_DIR: &str = "output-dir";
+const ARG_ARCHIVE: &str = "archive";
+const ARG_DECOMPRESS: &str = "decompress";
+#[cfg(target_os = "linux")]
+const ARG_LOG: &str = "log";
+const ARG_STATE: &str = "state";
+const ARG_TIME_BUDGET: &str = "time-budget";
+const ARG_BYTE_BUDGET: &str = "byte-budget";
+const ARG_XATTR: &str = "xattr";
+const ARG_SRI: &str = "sri";
+const ARG_ROOT: &str = "root";
+const ARG_DIGEST: &str = "digest";
+const ARG_LAYOUT: &str = "layout";
+const ARG_GIT: &str = "git";
+
+// Files are watched with inotify, which only exists on Linux
+#[cfg(target_os = "linux")]
+fn watch_subcommand() -> Option<Command> {
+    return Some(
+        Command::new(COMMAND_WATCH)
+            .about("Verify files again each time they are changed")
+            .arg(Arg::new(ARG_INPUT_FILE)
+                .short('i')
+                .long("input")
+                .num_args(1)
+                .value_parser(clap::value_parser!(PathBuf))
+                .required(true)
+                .help("A file containing reference hashes"))
+            .arg(Arg::new(ARG_LOG)
+                .long("log")
+                .num_args(1)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Also appends reported violations to this file"))
+            .arg(Arg::new(ARG_SHA_512)
+                .long("sha512")
+                .num_args(0)
+                .help("Uses SHA512"))
+            .arg(Arg::new(ARG_ALGO)
+                .long("algo")
+                .num_args(1)
+                .value_parser([ALGO_ARG_SHA1, ALGO_ARG_SHA256, ALGO_ARG_SHA384, ALGO_ARG_SHA512, ALGO_ARG_BLAKE3])
+                .conflicts_with(ARG_SHA_512)
+                .help("Hash algorithm"))
+            .arg(Arg::new(ARG_USE_BSD)
+                .long("use-bsd")
+                .num_args(0)
+                .help("Uses BSD format")));
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch_subcommand() -> Option<Command> {
+    return None;
+}
 
 fn main() {
     let mut app = Command::new("rs256sum")
@@ -191,6 +1452,7 @@ fn main() {
                     .short('i')
                     .long("input")
                     .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
                     .help("A file containing reference hashes"))
                 .arg(Arg::new(ARG_SHA_512)
                     .long("sha512")
@@ -203,7 +1465,73 @@ fn main() {
                 .arg(Arg::new(ARG_FROM_STDIN)
                     .long("from-stdin")
                     .num_args(0)
-                    .help("Reads reference data from stdin")))
+                    .help("Reads reference data from stdin"))
+                .arg(Arg::new(ARG_SIGNATURE)
+                    .short('s')
+                    .long("signature")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("A signify, minisign or PGP clearsigned signature of the reference data"))
+                .arg(Arg::new(ARG_PUB_KEY)
+                    .short('p')
+                    .long("pub-key")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .visible_alias("keyring")
+                    .help("Public key or PGP keyring used to check the signature"))
+                .arg(Arg::new(ARG_SIG_TYPE)
+                    .long("sig-type")
+                    .num_args(1)
+                    .value_parser([signature::SIG_TYPE_SIGNIFY, signature::SIG_TYPE_MINISIGN, signature::SIG_TYPE_PGP])
+                    .default_value(signature::SIG_TYPE_SIGNIFY)
+                    .help("Type of the signature"))
+                .arg(Arg::new(ARG_CHUNKED)
+                    .long("chunked")
+                    .num_args(0)
+                    .conflicts_with(ARG_TREE)
+                    .help("Uses chunk hashes to report the byte ranges of failed files"))
+                .arg(Arg::new(ARG_TREE)
+                    .long("tree")
+                    .num_args(1)
+                    .help("Reference data contains hash tree roots with leaves of this size"))
+                .arg(Arg::new(ARG_ALGO)
+                    .long("algo")
+                    .num_args(1)
+                    .value_parser([ALGO_ARG_SHA1, ALGO_ARG_SHA256, ALGO_ARG_SHA384, ALGO_ARG_SHA512, ALGO_ARG_BLAKE3])
+                    .conflicts_with(ARG_SHA_512)
+                    .help("Hash algorithm"))
+                .arg(Arg::new(ARG_ARCHIVE)
+                    .long("archive")
+                    .num_args(0)
+                    .help("Verifies members of tar and zip archives named <archive>!<path>"))
+                .arg(Arg::new(ARG_DECOMPRESS)
+                    .long("decompress")
+                    .num_args(0)
+                    .conflicts_with(ARG_ARCHIVE)
+                    .help("Verifies the decompressed data of files compressed with gzip, zstd or xz"))
+                .arg(Arg::new(ARG_XATTR)
+                    .long("xattr")
+                    .num_args(0)
+                    .requires(ARG_FILES)
+                    .conflicts_with_all([ARG_INPUT_FILE, ARG_FROM_STDIN, ARG_SIGNATURE, ARG_CHUNKED, ARG_ARCHIVE, ARG_DECOMPRESS, ARG_ALGO, ARG_SHA_512, ARG_TREE, ARG_GIT])
+                    .help("Verifies files against the hashes stored in their extended attributes"))
+                .arg(Arg::new(ARG_FILES)
+                    .short('f')
+                    .long("files")
+                    .num_args(1..)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .requires(ARG_XATTR)
+                    .help("Names of files to verify with --xattr"))
+                .arg(Arg::new(ARG_SRI)
+                    .long("sri")
+                    .num_args(0)
+                    .conflicts_with_all([ARG_USE_BSD, ARG_TREE])
+                    .help("Reference data contains SRI integrity strings"))
+                .arg(Arg::new(ARG_GIT)
+                    .long("git")
+                    .num_args(0)
+                    .conflicts_with_all([ARG_SHA_512, ARG_TREE, ARG_CHUNKED])
+                    .help("Reference data contains git blob IDs (SHA1 unless --algo sha256 is given)")))
         .subcommand(
             Command::new(COMMAND_GEN)
                 .about("Generate reference data")        
@@ -211,7 +1539,8 @@ fn main() {
                     .short('f')
                     .long("files")
                     .num_args(1..)
-                    .help("Names of files to hash"))
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("Names of files to hash, - means stdin"))
                 .arg(Arg::new(ARG_SHA_512)
                     .long("sha512")
                     .num_args(0)
@@ -223,7 +1552,229 @@ fn main() {
                 .arg(Arg::new(ARG_FROM_STDIN)
                     .long("from-stdin")
                     .num_args(0)
-                    .help("Reads names of files to hash from stdin")));
+                    .help("Reads names of files to hash from stdin"))
+                .arg(Arg::new(ARG_FILES_FROM)
+                    .long("files-from")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("Reads names of files to hash from this file, - means stdin"))
+                .arg(Arg::new(ARG_NULL)
+                    .short('0')
+                    .long("null")
+                    .num_args(0)
+                    .help("Names of files to hash are separated by NUL instead of newline"))
+                .arg(Arg::new(ARG_FAIL_FAST)
+                    .long("fail-fast")
+                    .num_args(0)
+                    .help("Stops at the first file which can not be hashed"))
+                .arg(Arg::new(ARG_ENCODING)
+                    .long("encoding")
+                    .num_args(1)
+                    .value_parser([encoding::ENCODING_HEX, encoding::ENCODING_BASE64, encoding::ENCODING_BASE32, encoding::ENCODING_BASE58])
+                    .default_value(encoding::ENCODING_HEX)
+                    .help("Encoding of the hashes"))
+                .arg(Arg::new(ARG_ALGO)
+                    .long("algo")
+                    .num_args(1)
+                    .value_delimiter(',')
+                    .value_parser([ALGO_ARG_SHA1, ALGO_ARG_SHA256, ALGO_ARG_SHA384, ALGO_ARG_SHA512, ALGO_ARG_BLAKE3])
+                    .conflicts_with(ARG_SHA_512)
+                    .help("Comma separated list of hash algorithms which are calculated in one pass"))
+                .arg(Arg::new(ARG_OUTPUT_DIR)
+                    .long("output-dir")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("Writes the reference data for each algorithm to <algorithm>SUMS in this directory"))
+                .arg(Arg::new(ARG_ARCHIVE)
+                    .long("archive")
+                    .num_args(0)
+                    .help("Hashes the members of tar and zip archives instead of the archives"))
+                .arg(Arg::new(ARG_DECOMPRESS)
+                    .long("decompress")
+                    .num_args(0)
+                    .conflicts_with(ARG_ARCHIVE)
+                    .help("Hashes the decompressed data of files compressed with gzip, zstd or xz"))
+                .arg(Arg::new(ARG_XATTR)
+                    .long("xattr")
+                    .num_args(0)
+                    .help("Also stores the hashes in an extended attribute of the files"))
+                .arg(Arg::new(ARG_SRI)
+                    .long("sri")
+                    .num_args(0)
+                    .conflicts_with_all([ARG_USE_BSD, ARG_TREE, ARG_CHUNK_SIZE, ARG_OUTPUT_DIR, ARG_ENCODING])
+                    .help("Writes SRI integrity strings containing the hashes of all algorithms"))
+                .arg(Arg::new(ARG_DIGEST)
+                    .long("digest")
+                    .num_args(0)
+                    .conflicts_with_all([ARG_SRI, ARG_TREE, ARG_GIT])
+                    .help("Writes hashes in digest notation <algorithm>:<hash>"))
+                .arg(Arg::new(ARG_GIT)
+                    .long("git")
+                    .num_args(0)
+                    .conflicts_with_all([ARG_SHA_512, ARG_TREE, ARG_CHUNK_SIZE, ARG_SRI])
+                    .help("Writes git blob IDs (SHA1 unless --algo sha256 is given)"))
+                .arg(Arg::new(ARG_CHUNK_SIZE)
+                    .long("chunk-size")
+                    .num_args(1)
+                    .help("Also records hashes of chunks of this size (suffixes K, M and G are allowed)"))
+                .arg(Arg::new(ARG_TREE)
+                    .long("tree")
+                    .num_args(1)
+                    .help("Uses the root of a hash tree with leaves of this size"))
+                .arg(Arg::new(ARG_OFFSET)
+                    .long("offset")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(u64))
+                    .help("Only hashes data from this offset on"))
+                .arg(Arg::new(ARG_LENGTH)
+                    .long("length")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(u64))
+                    .help("Only hashes this number of bytes"))
+                .arg(Arg::new(ARG_EXCLUDE_RANGE)
+                    .long("exclude-range")
+                    .num_args(1)
+                    .action(clap::ArgAction::Append)
+                    .help("Does not hash the bytes in this range given as <offset>+<length>")))
+        .subcommand(
+            Command::new(COMMAND_PROOF)
+                .about("Print an inclusion proof for a leaf of a hash tree")
+                .arg(Arg::new(ARG_FILE)
+                    .short('f')
+                    .long("file")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .required(true)
+                    .help("Name of the file to hash"))
+                .arg(Arg::new(ARG_TREE)
+                    .long("tree")
+                    .num_args(1)
+                    .required(true)
+                    .help("Size of the leaves of the hash tree"))
+                .arg(Arg::new(ARG_LEAF)
+                    .long("leaf")
+                    .num_args(1)
+                    .required(true)
+                    .value_parser(clap::value_parser!(usize))
+                    .help("Index of the leaf to prove"))
+                .arg(Arg::new(ARG_SHA_512)
+                    .long("sha512")
+                    .num_args(0)
+                    .help("Uses SHA512")))
+        .subcommand(
+            Command::new(COMMAND_TREE_HASH)
+                .about("Generate one hash for each directory tree")
+                .arg(Arg::new(ARG_DIRS)
+                    .short('d')
+                    .long("dirs")
+                    .num_args(1..)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .required(true)
+                    .help("Names of directories to hash"))
+                .arg(Arg::new(ARG_MODES)
+                    .long("modes")
+                    .num_args(0)
+                    .help("Includes permission bits in the hash"))
+                .arg(Arg::new(ARG_SHA_512)
+                    .long("sha512")
+                    .num_args(0)
+                    .help("Uses SHA512"))
+                .arg(Arg::new(ARG_ALGO)
+                    .long("algo")
+                    .num_args(1)
+                    .value_parser([ALGO_ARG_SHA1, ALGO_ARG_SHA256, ALGO_ARG_SHA384, ALGO_ARG_SHA512, ALGO_ARG_BLAKE3])
+                    .conflicts_with(ARG_SHA_512)
+                    .help("Hash algorithm"))
+                .arg(Arg::new(ARG_GIT)
+                    .long("git")
+                    .num_args(0)
+                    .conflicts_with_all([ARG_SHA_512, ARG_MODES])
+                    .help("Calculates git tree IDs (SHA1 unless --algo sha256 is given)"))
+                .arg(Arg::new(ARG_USE_BSD)
+                    .long("use-bsd")
+                    .num_args(0)
+                    .help("Uses BSD format")))
+        .subcommand(
+            Command::new(COMMAND_CHUNKS)
+                .about("Split files into content defined chunks and report deduplication statistics")
+                .arg(Arg::new(ARG_FILES)
+                    .short('f')
+                    .long("files")
+                    .num_args(1..)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .required(true)
+                    .help("Names of files to chunk"))
+                .arg(Arg::new(ARG_AVG_SIZE)
+                    .long("avg-size")
+                    .num_args(1)
+                    .help("Average chunk size, a power of two (default 8K)"))
+                .arg(Arg::new(ARG_SHA_512)
+                    .long("sha512")
+                    .num_args(0)
+                    .help("Uses SHA512")))
+        .subcommands(watch_subcommand())
+        .subcommand(
+            Command::new(COMMAND_SCRUB)
+                .about("Verify the least recently verified files within a time or I/O budget")
+                .arg(Arg::new(ARG_INPUT_FILE)
+                    .short('i')
+                    .long("input")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .required(true)
+                    .help("A file containing reference hashes"))
+                .arg(Arg::new(ARG_STATE)
+                    .long("state")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .required(true)
+                    .help("File which records when each file was verified"))
+                .arg(Arg::new(ARG_TIME_BUDGET)
+                    .long("time-budget")
+                    .num_args(1)
+                    .help("No further files are verified after this time (suffixes s, m and h are allowed)"))
+                .arg(Arg::new(ARG_BYTE_BUDGET)
+                    .long("byte-budget")
+                    .num_args(1)
+                    .help("No further files are verified after this number of bytes (suffixes K, M and G are allowed)"))
+                .arg(Arg::new(ARG_SHA_512)
+                    .long("sha512")
+                    .num_args(0)
+                    .help("Uses SHA512"))
+                .arg(Arg::new(ARG_ALGO)
+                    .long("algo")
+                    .num_args(1)
+                    .value_parser([ALGO_ARG_SHA1, ALGO_ARG_SHA256, ALGO_ARG_SHA384, ALGO_ARG_SHA512, ALGO_ARG_BLAKE3])
+                    .conflicts_with(ARG_SHA_512)
+                    .help("Hash algorithm"))
+                .arg(Arg::new(ARG_USE_BSD)
+                    .long("use-bsd")
+                    .num_args(0)
+                    .help("Uses BSD format")))
+        .subcommand(
+            Command::new(COMMAND_CHECK_HTML)
+                .about("Check the integrity attributes of scripts and stylesheets in HTML files")
+                .arg(Arg::new(ARG_FILES)
+                    .short('f')
+                    .long("files")
+                    .num_args(1..)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .required(true)
+                    .help("Names of HTML files to check"))
+                .arg(Arg::new(ARG_ROOT)
+                    .long("root")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("Directory which absolute paths in URLs refer to (default: the directory of the HTML file)")))
+        .subcommand(
+            Command::new(COMMAND_VERIFY_OCI)
+                .about("Verify the blobs and references of an OCI image layout")
+                .arg(Arg::new(ARG_LAYOUT)
+                    .long("layout")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .required(true)
+                    .help("Directory containing the image layout")));
 
     let matches = app.clone().get_matches();
     let subcommand = matches.subcommand();
@@ -235,13 +1786,35 @@ fn main() {
         Some((COMMAND_VERIFY, verify_matches)) => {
             verify_command(verify_matches)
         },
+        Some((COMMAND_PROOF, proof_matches)) => {
+            proof_command(proof_matches)
+        },
+        Some((COMMAND_TREE_HASH, tree_matches)) => {
+            tree_hash_command(tree_matches)
+        },
+        Some((COMMAND_CHUNKS, chunks_matches)) => {
+            chunks_command(chunks_matches)
+        },
+        #[cfg(target_os = "linux")]
+        Some((COMMAND_WATCH, watch_matches)) => {
+            watch_command(watch_matches)
+        },
+        Some((COMMAND_SCRUB, scrub_matches)) => {
+            scrub_command(scrub_matches)
+        },
+        Some((COMMAND_CHECK_HTML, html_matches)) => {
+            check_html_command(html_matches)
+        },
+        Some((COMMAND_VERIFY_OCI, oci_matches)) => {
+            verify_oci_command(oci_matches)
+        },
         _ => {
             match app.print_long_help() {
                 Err(e) => eprintln!("{}", e),
-                _ => eprintln!("")
+                _ => eprintln!()
             }
 
-            PROG_RETURN_ERR
+            PROG_RETURN_USAGE
         }
     };
 
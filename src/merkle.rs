@@ -0,0 +1,83 @@
+use digest::DynDigest;
+
+// A binary hash tree over the hashes of fixed size leaves. An inner node is the hash of
+// the concatenation of its children. A node without a sibling is promoted unchanged to
+// the next level. With SHA256 and a leaf size of 1 MiB the root is the tree hash used
+// by Amazon Glacier.
+pub struct MerkleTree {
+    levels: Vec<Vec<Box<[u8]>>>
+}
+
+pub enum ProofStep {
+    // The sibling is the left child, i.e. it has to be prepended
+    Left(Box<[u8]>),
+    // The sibling is the right child, i.e. it has to be appended
+    Right(Box<[u8]>)
+}
+
+fn hash_pair(digest: &mut dyn DynDigest, left: &[u8], right: &[u8]) -> Box<[u8]> {
+    digest.update(left);
+    digest.update(right);
+
+    return digest.finalize_reset();
+}
+
+impl MerkleTree {
+    // leaves must not be empty
+    pub fn new(leaves: Vec<Box<[u8]>>, digest: &mut dyn DynDigest) -> MerkleTree {
+        let mut levels = vec![leaves];
+        digest.reset();
+
+        while levels.last().unwrap().len() > 1 {
+            let next: Vec<Box<[u8]>> = levels.last().unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_pair(digest, left, right),
+                    _ => pair[0].clone()
+                })
+                .collect();
+
+            levels.push(next);
+        }
+
+        return MerkleTree { levels };
+    }
+
+    pub fn root(&self) -> &[u8] {
+        return &self.levels.last().unwrap()[0];
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        return self.levels[0].len();
+    }
+
+    pub fn leaf(&self, index: usize) -> Option<&[u8]> {
+        return self.levels[0].get(index).map(|l| &l[..]);
+    }
+
+    // Returns the sibling hashes needed to get from the leaf to the root
+    pub fn proof(&self, index: usize) -> Option<Vec<ProofStep>> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+
+        let mut res: Vec<ProofStep> = Vec::new();
+        let mut pos = index;
+
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = pos ^ 1;
+
+            if sibling < level.len() {
+                if sibling < pos {
+                    res.push(ProofStep::Left(level[sibling].clone()));
+                } else {
+                    res.push(ProofStep::Right(level[sibling].clone()));
+                }
+            }
+
+            pos /= 2;
+        }
+
+        return Some(res);
+    }
+}
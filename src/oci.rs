@@ -0,0 +1,269 @@
+use regex::Regex;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::escape::display_name;
+use crate::hasher::{FileHash, HashError};
+
+// An OCI image layout (https://github.com/opencontainers/image-spec/blob/main/image-layout.md)
+// is a directory containing the file oci-layout, the top level index index.json and the
+// directory blobs. The blob with the digest <algorithm>:<encoded> is stored in the file
+// blobs/<algorithm>/<encoded>.
+const LAYOUT_FILE: &str = "oci-layout";
+const INDEX_FILE: &str = "index.json";
+const BLOBS_DIR: &str = "blobs";
+const DIGEST_EXP: &str = "^([a-z0-9]+(?:[+._-][a-z0-9]+)*):([a-zA-Z0-9=_-]+)$";
+
+// Blobs of these types are indexes or manifests which reference further blobs
+const MANIFEST_TYPES: [&str; 4] = [
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+    "application/vnd.docker.distribution.manifest.v2+json"
+];
+
+pub enum OciReport {
+    BlobOk(PathBuf),
+    // The blob does not match its digest or could not be read
+    BlobFailed(PathBuf, HashError),
+    // The algorithm of the blob is not supported
+    Unsupported(PathBuf),
+    // Digest of a blob which does not exist and the name of the referencing index or manifest
+    Missing(String, String),
+    // Digest, size given in the descriptor and size of the blob
+    WrongSize(String, u64, u64),
+    Unreferenced(PathBuf),
+    // Name of the file and the problem
+    Malformed(String, String)
+}
+
+impl OciReport {
+    pub fn message(&self) -> String {
+        match self {
+            OciReport::BlobOk(path) => format!("{}: OK", display_name(path)),
+            OciReport::BlobFailed(path, HashError::HashVerifyFail(_)) => format!("{}: FAILED!!!", display_name(path)),
+            OciReport::BlobFailed(path, e) => format!("{}: {}", display_name(path), e.message()),
+            OciReport::Unsupported(path) => format!("{}: Unsupported digest algorithm", display_name(path)),
+            OciReport::Missing(digest, referrer) => format!("{}: Blob referenced by {} does not exist", digest, referrer),
+            OciReport::WrongSize(digest, expected, actual) => format!("{}: Blob has {} bytes instead of {}", digest, actual, expected),
+            OciReport::Unreferenced(path) => format!("{}: Blob is not referenced", display_name(path)),
+            OciReport::Malformed(name, problem) => format!("{}: {}", name, problem)
+        }
+    }
+}
+
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64
+}
+
+impl Descriptor {
+    fn from_json(value: &Value) -> Option<Descriptor> {
+        return Some(Descriptor {
+            media_type: value.get("mediaType")?.as_str()?.to_string(),
+            digest: value.get("digest")?.as_str()?.to_string(),
+            size: value.get("size")?.as_u64()?
+        });
+    }
+}
+
+// Returns the descriptors contained in an index or a manifest. None is returned if one of
+// them is malformed.
+pub fn child_descriptors(value: &Value) -> Option<Vec<Descriptor>> {
+    let mut res = Vec::new();
+
+    for key in ["manifests", "layers", "blobs"] {
+        if let Some(list) = value.get(key) {
+            for d in list.as_array()? {
+                res.push(Descriptor::from_json(d)?);
+            }
+        }
+    }
+
+    for key in ["config", "subject"] {
+        if let Some(d) = value.get(key) {
+            res.push(Descriptor::from_json(d)?);
+        }
+    }
+
+    return Some(res);
+}
+
+fn read_json(file_name: &Path) -> Result<Value, String> {
+    let data = fs::read(file_name).map_err(|e| e.to_string())?;
+
+    return serde_json::from_slice(&data).map_err(|e| format!("Invalid JSON: {}", e));
+}
+
+struct Blob {
+    path: PathBuf,
+    size: u64,
+    ok: bool
+}
+
+pub struct LayoutChecker<'a> {
+    layout: PathBuf,
+    hasher_for: &'a mut dyn FnMut(&str) -> Option<Box<dyn FileHash>>,
+    digest_exp: Regex,
+    blobs: HashMap<String, Blob>,
+    reports: Vec<OciReport>
+}
+
+impl<'a> LayoutChecker<'a> {
+    // hasher_for returns a hasher for the algorithm of a digest if it is supported
+    pub fn new(layout: &Path, hasher_for: &'a mut dyn FnMut(&str) -> Option<Box<dyn FileHash>>) -> LayoutChecker<'a> {
+        return LayoutChecker {
+            layout: layout.to_path_buf(),
+            hasher_for,
+            digest_exp: Regex::new(DIGEST_EXP).unwrap(),
+            blobs: HashMap::new(),
+            reports: Vec::new()
+        };
+    }
+
+    // Checks every blob against its file name and every reference of the indexes and
+    // manifests reachable from index.json
+    pub fn check(mut self) -> Vec<OciReport> {
+        match read_json(&self.layout.join(LAYOUT_FILE)) {
+            Ok(v) if v.get("imageLayoutVersion").is_some_and(|x| x.is_string()) => {},
+            Ok(_) => {
+                self.reports.push(OciReport::Malformed(LAYOUT_FILE.to_string(), "imageLayoutVersion is missing".to_string()));
+                return self.reports;
+            },
+            Err(e) => {
+                self.reports.push(OciReport::Malformed(LAYOUT_FILE.to_string(), e));
+                return self.reports;
+            }
+        }
+
+        if let Err(e) = self.check_blobs() {
+            self.reports.push(OciReport::Malformed(BLOBS_DIR.to_string(), e));
+            return self.reports;
+        }
+
+        let referenced = self.check_references();
+        let mut unreferenced: Vec<&Blob> = self.blobs.iter().filter(|(d, _)| !referenced.contains(*d)).map(|(_, b)| b).collect();
+        unreferenced.sort_by(|a, b| a.path.cmp(&b.path));
+        let unreferenced_reports: Vec<OciReport> = unreferenced.into_iter().map(|b| OciReport::Unreferenced(b.path.clone())).collect();
+        self.reports.extend(unreferenced_reports);
+
+        return self.reports;
+    }
+
+    fn sorted_entries(dir: &Path) -> Result<Vec<fs::DirEntry>, String> {
+        let mut entries = fs::read_dir(dir)
+            .and_then(|d| d.collect::<Result<Vec<fs::DirEntry>, _>>())
+            .map_err(|e| format!("{}: {}", display_name(dir), e))?;
+        entries.sort_by_key(|e| e.file_name());
+
+        return Ok(entries);
+    }
+
+    fn check_blobs(&mut self) -> Result<(), String> {
+        for algo_entry in LayoutChecker::sorted_entries(&self.layout.join(BLOBS_DIR))? {
+            // Stray entries are reported, the remaining blobs are checked anyway
+            if !algo_entry.path().is_dir() {
+                self.reports.push(OciReport::Malformed(display_name(&algo_entry.path()), "Not an algorithm directory".to_string()));
+                continue;
+            }
+
+            let blob_entries = match LayoutChecker::sorted_entries(&algo_entry.path()) {
+                Ok(e) => e,
+                Err(e) => {
+                    self.reports.push(OciReport::Malformed(BLOBS_DIR.to_string(), e));
+                    continue;
+                }
+            };
+
+            for blob_entry in blob_entries {
+                let path = blob_entry.path();
+                let digest = format!("{}:{}", algo_entry.file_name().to_string_lossy(), blob_entry.file_name().to_string_lossy());
+                let size = blob_entry.metadata().map(|m| m.len()).unwrap_or(0);
+                let report = self.check_blob(&path, &digest);
+                let ok = matches!(report, OciReport::BlobOk(_));
+
+                self.reports.push(report);
+                self.blobs.insert(digest, Blob { path, size, ok });
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn check_blob(&mut self, path: &Path, digest: &str) -> OciReport {
+        let (algo, encoded) = match self.digest_exp.captures(digest) {
+            Some(c) => (c[1].to_string(), c[2].to_string()),
+            None => return OciReport::Malformed(display_name(path), "File name is not a valid digest".to_string())
+        };
+
+        // The encoded part of a digest is always written in lowercase hex
+        if !encoded.bytes().all(|c| c.is_ascii_digit() || (b'a'..=b'f').contains(&c)) {
+            return OciReport::Malformed(display_name(path), "File name is not a lowercase hex hash".to_string());
+        }
+
+        let mut h = match (self.hasher_for)(&algo) {
+            Some(h) => h,
+            None => return OciReport::Unsupported(path.to_path_buf())
+        };
+
+        let hash = match h.hash_file(path) {
+            Ok(hash) => hash,
+            Err(e) => return OciReport::BlobFailed(path.to_path_buf(), e)
+        };
+
+        if encoded.len() != hash.len() {
+            return OciReport::Malformed(display_name(path), format!("File name is not a {} hash", h.get_algo()));
+        }
+
+        if encoded != hash {
+            return OciReport::BlobFailed(path.to_path_buf(), HashError::HashVerifyFail(display_name(path)));
+        }
+
+        return OciReport::BlobOk(path.to_path_buf());
+    }
+
+    // Follows the references starting at index.json and returns the digests of all
+    // referenced blobs
+    fn check_references(&mut self) -> HashSet<String> {
+        let mut referenced: HashSet<String> = HashSet::new();
+        let mut pending: Vec<(String, PathBuf)> = vec![(INDEX_FILE.to_string(), self.layout.join(INDEX_FILE))];
+
+        while let Some((name, file_name)) = pending.pop() {
+            let children = match read_json(&file_name).and_then(|v| child_descriptors(&v).ok_or("Malformed descriptor".to_string())) {
+                Ok(c) => c,
+                Err(e) => {
+                    self.reports.push(OciReport::Malformed(name, e));
+                    continue;
+                }
+            };
+
+            for d in children {
+                if !self.digest_exp.is_match(&d.digest) {
+                    self.reports.push(OciReport::Malformed(name.clone(), format!("Invalid digest '{}'", d.digest)));
+                    continue;
+                }
+
+                let blob = match self.blobs.get(&d.digest) {
+                    Some(b) => b,
+                    None => {
+                        self.reports.push(OciReport::Missing(d.digest.clone(), name.clone()));
+                        continue;
+                    }
+                };
+
+                if blob.size != d.size {
+                    self.reports.push(OciReport::WrongSize(d.digest.clone(), d.size, blob.size));
+                }
+
+                // The content of a corrupt blob can not be trusted
+                if referenced.insert(d.digest.clone()) && blob.ok && MANIFEST_TYPES.contains(&d.media_type.as_str()) {
+                    pending.push((d.digest.clone(), blob.path.clone()));
+                }
+            }
+        }
+
+        return referenced;
+    }
+}
@@ -1,65 +1,236 @@
 use std::io::{self, BufRead, Read};
 use std::rc::Rc;
 use std::cell::RefCell;
-use crate::formatter::HashLineFormatter;
+use std::error::Error;
+use std::fmt;
+use crate::formatter::{HashLineFormatter, ParseError};
+use crate::chunked::is_chunk_line;
+use crate::region::{Region, is_region_line};
+use std::iter::Peekable;
+use std::path::PathBuf;
+use crate::escape::escape_raw_line;
 
-pub struct RefFileIter<R : Read> {
-    parser: Rc<dyn HashLineFormatter>,
-    line_iter: Rc<RefCell<std::io::Lines<io::BufReader<R>>>>    
+const PGP_MSG_BEGIN: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+const PGP_SIG_BEGIN: &str = "-----BEGIN PGP SIGNATURE-----";
+const PGP_SIG_END: &str = "-----END PGP SIGNATURE-----";
+
+#[derive(PartialEq)]
+enum ArmorState {
+    Text,
+    ArmorHeader,
+    SignedText,
+    Signature
 }
 
-impl<R : Read> Iterator for RefFileIter<R> {
-    type Item = (String, String);
-
-    fn next(&mut self) -> Option<(String, String)> {
-        let line_res = self.line_iter.borrow_mut().next();
-        let line_raw = match line_res {
-            Some(d) => match d {
-                Err(e) => {
-                    eprintln!("{}", e);
-                    return None
+// Removes a PGP clearsign envelope (RFC 4880, section 7) from the lines of the
+// underlying reader and undoes the dash-escaping of the signed text. Lines outside
+// of an envelope are passed through unchanged. Every line is returned together
+// with its line number in the underlying reader.
+pub struct ArmorLines<R : Read> {
+    lines: std::io::Split<io::BufReader<R>>,
+    state: ArmorState,
+    line_no: usize
+}
+
+impl<R : Read> ArmorLines<R> {
+    pub fn new(s: R) -> Self {
+        return ArmorLines {
+            lines: io::BufReader::new(s).split(b'\n'),
+            state: ArmorState::Text,
+            line_no: 0
+        }
+    }
+
+    fn next_line(&mut self) -> Option<io::Result<String>> {
+        let mut line = match self.lines.next()? {
+            Ok(l) => l,
+            Err(e) => return Some(Err(e))
+        };
+
+        self.line_no += 1;
+
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+
+        // File names which are not valid UTF-8 are escaped
+        return match String::from_utf8(line) {
+            Ok(l) => Some(Ok(l)),
+            Err(e) => Some(Ok(escape_raw_line(e.as_bytes())))
+        };
+    }
+}
+
+impl<R : Read> Iterator for ArmorLines<R> {
+    type Item = io::Result<(usize, String)>;
+
+    fn next(&mut self) -> Option<io::Result<(usize, String)>> {
+        loop {
+            let line = match self.next_line()? {
+                Ok(l) => l,
+                Err(e) => return Some(Err(e))
+            };
+            let trimmed = line.trim_end();
+
+            match self.state {
+                ArmorState::Text => {
+                    if trimmed == PGP_MSG_BEGIN {
+                        self.state = ArmorState::ArmorHeader;
+                    } else {
+                        return Some(Ok((self.line_no, line)));
+                    }
                 },
-                Ok(d) => String::from(d)
+                ArmorState::ArmorHeader => {
+                    if trimmed.is_empty() {
+                        self.state = ArmorState::SignedText;
+                    }
+                },
+                ArmorState::SignedText => {
+                    if trimmed == PGP_SIG_BEGIN {
+                        self.state = ArmorState::Signature;
+                    } else if let Some(unescaped) = line.strip_prefix("- ") {
+                        return Some(Ok((self.line_no, String::from(unescaped))));
+                    } else {
+                        return Some(Ok((self.line_no, line)));
+                    }
+                },
+                ArmorState::Signature => {
+                    if trimmed == PGP_SIG_END {
+                        self.state = ArmorState::Text;
+                    }
+                }
             }
-            None => return None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RefFileError {
+    Malformed(usize, ParseError),
+    ReadError(io::Error)
+}
+
+impl RefFileError {
+    pub fn message(&self) -> String {
+        match self {
+            RefFileError::Malformed(line_no, e) => format!("Line {}: {}", line_no, e.message()),
+            RefFileError::ReadError(e) => format!("Unable to read reference data: {}", e)
+        }
+    }
+}
+
+impl fmt::Display for RefFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.message());
+    }
+}
+
+impl Error for RefFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return match self {
+            RefFileError::Malformed(_, e) => Some(e),
+            RefFileError::ReadError(e) => Some(e)
         };
+    }
+}
+
+pub struct RefEntry {
+    pub line_no: usize,
+    pub file_name: PathBuf,
+    pub hash: String,
+    pub region: Region
+}
 
-        let res = self.parser.parse(&line_raw);
+// Reads the next line which is not a chunk hash. After a read error the iteration ends.
+pub fn next_entry_line<R : Read>(lines: &mut Peekable<ArmorLines<R>>, failed: &mut bool) -> Option<Result<(usize, String), RefFileError>> {
+    if *failed {
+        return None;
+    }
 
-        let item = match res {
+    loop {
+        match lines.next()? {
+            Ok((_, l)) if is_chunk_line(&l) => continue,
+            Ok(l) => return Some(Ok(l)),
             Err(e) => {
-                eprintln!("{}", e.message());
-                return None
+                *failed = true;
+                return Some(Err(RefFileError::ReadError(e)));
+            }
+        }
+    }
+}
+
+// Skips the region line following a malformed entry line, so that it is not reported as
+// another malformed line
+pub fn skip_region_line<R : Read>(lines: &mut Peekable<ArmorLines<R>>, entry_line: &str) {
+    if !is_region_line(entry_line) {
+        lines.next_if(|l| matches!(l, Ok((_, l)) if is_region_line(l)));
+    }
+}
+
+pub struct RefFileIter<R : Read> {
+    parser: Rc<dyn HashLineFormatter>,
+    line_iter: Rc<RefCell<Peekable<ArmorLines<R>>>>,
+    failed: Rc<RefCell<bool>>
+}
+
+impl<R : Read> Iterator for RefFileIter<R> {
+    type Item = Result<RefEntry, RefFileError>;
+
+    fn next(&mut self) -> Option<Result<RefEntry, RefFileError>> {
+        let mut lines = self.line_iter.borrow_mut();
+
+        // Chunk hashes are only evaluated by ChunkedRefFile
+        let (line_no, line_raw) = match next_entry_line(&mut lines, &mut self.failed.borrow_mut())? {
+            Ok(l) => l,
+            Err(e) => return Some(Err(e))
+        };
+
+        let (file_name, hash) = match self.parser.parse(&line_raw) {
+            Err(e) => {
+                skip_region_line(&mut lines, &line_raw);
+                return Some(Err(RefFileError::Malformed(line_no, e)));
             },
-            Ok(i) => Some(i),
+            Ok(i) => i,
         };
 
-        return item;
+        let mut region = Region::default();
+
+        if let Some(Ok((region_line_no, l))) = lines.next_if(|l| matches!(l, Ok((_, l)) if is_region_line(l))) {
+            region = match Region::parse(&l) {
+                Ok(r) => r,
+                Err(e) => return Some(Err(RefFileError::Malformed(region_line_no, e)))
+            };
+        }
+
+        return Some(Ok(RefEntry { line_no, file_name, hash, region }));
     }  
 }
 
 pub struct RefFile<R : Read> {
     parser: Rc<dyn HashLineFormatter>,
-    line_iter: Rc<RefCell<std::io::Lines<io::BufReader<R>>>>
+    line_iter: Rc<RefCell<Peekable<ArmorLines<R>>>>,
+    failed: Rc<RefCell<bool>>
 }
 
 impl<R : Read> RefFile<R> {
     pub fn new(s: R, p: &Rc<dyn HashLineFormatter>) -> Self {
         return RefFile {
             parser: p.clone(),
-            line_iter: Rc::new(RefCell::new(io::BufReader::new(s).lines()))
+            line_iter: Rc::new(RefCell::new(ArmorLines::new(s).peekable())),
+            failed: Rc::new(RefCell::new(false))
         }
     }
 }
 
 impl<R : Read> IntoIterator for &RefFile<R> {
-    type Item = (String, String);
+    type Item = Result<RefEntry, RefFileError>;
     type IntoIter = RefFileIter<R>;
 
     fn into_iter(self) -> Self::IntoIter {
         return RefFileIter {
             parser: self.parser.clone(),
-            line_iter: self.line_iter.clone()
+            line_iter: self.line_iter.clone(),
+            failed: self.failed.clone()
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,152 @@
+use std::io::{self, Read};
+use crate::formatter::ParseError;
+
+// Reference data for a region of a file is followed by a line of the form
+// REGION <offset> <length or -> [<exclude offset>+<exclude length> ...]
+// where a length of - means "up to the end of the file". Offsets of excluded
+// ranges are absolute positions in the file.
+pub const REGION_PREFIX: &str = "REGION ";
+const TO_END: &str = "-";
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Region {
+    pub offset: u64,
+    pub length: Option<u64>,
+    pub excludes: Vec<(u64, u64)>
+}
+
+pub fn is_region_line(line: &str) -> bool {
+    return line.starts_with(REGION_PREFIX);
+}
+
+// Parses an excluded range of the form <offset>+<length>
+pub fn parse_exclude(range: &str) -> Option<(u64, u64)> {
+    let (offset, length) = range.split_once('+')?;
+
+    return Some((offset.parse().ok()?, length.parse().ok()?));
+}
+
+impl Region {
+    pub fn new(offset: u64, length: Option<u64>, excludes: Vec<(u64, u64)>) -> Region {
+        let mut excludes = excludes;
+        excludes.sort();
+
+        return Region { offset, length, excludes };
+    }
+
+    // The whole file
+    pub fn is_whole(&self) -> bool {
+        return (self.offset == 0) && self.length.is_none() && self.excludes.is_empty();
+    }
+
+    pub fn format(&self) -> String {
+        let mut res = format!("{}{} {}", REGION_PREFIX, self.offset, self.length.map_or(String::from(TO_END), |l| l.to_string()));
+
+        for (offset, length) in &self.excludes {
+            res.push_str(&format!(" {}+{}", offset, length));
+        }
+
+        return res;
+    }
+
+    pub fn parse(line: &str) -> Result<Region, ParseError> {
+        let format_err = || ParseError::FormatError(String::from(line));
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if (fields.len() < 3) || (fields[0] != REGION_PREFIX.trim()) {
+            return Err(format_err());
+        }
+
+        let offset: u64 = fields[1].parse().map_err(|_| format_err())?;
+        let length: Option<u64> = match fields[2] {
+            TO_END => None,
+            l => Some(l.parse().map_err(|_| format_err())?)
+        };
+
+        let mut excludes: Vec<(u64, u64)> = Vec::new();
+        for e in &fields[3..] {
+            excludes.push(parse_exclude(e).ok_or_else(format_err)?);
+        }
+
+        return Ok(Region::new(offset, length, excludes));
+    }
+}
+
+pub fn region_eof_error() -> io::Error {
+    return io::Error::new(io::ErrorKind::UnexpectedEof, "The data ends before the end of the region");
+}
+
+// Skips the data in front of a region for readers which can not seek
+pub fn skip_to_region<R : Read>(r: &mut R, region: &Region) -> io::Result<()> {
+    if io::copy(&mut r.take(region.offset), &mut io::sink())? < region.offset {
+        return Err(region_eof_error());
+    }
+
+    return Ok(());
+}
+
+// Reads the data of a region from a reader which is positioned at the region offset.
+// If the data ends before the end of a region of fixed length, an error is returned.
+pub struct RegionReader<R : Read> {
+    inner: io::Take<R>,
+    pos: u64,
+    to_end: bool,
+    excludes: Vec<(u64, u64)>
+}
+
+impl<R : Read> RegionReader<R> {
+    pub fn new(inner: R, region: &Region) -> RegionReader<R> {
+        return RegionReader {
+            inner: inner.take(region.length.unwrap_or(u64::MAX)),
+            pos: region.offset,
+            to_end: region.length.is_none(),
+            excludes: region.excludes.clone()
+        }
+    }
+}
+
+impl<R : Read> Read for RegionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let bytes_read = self.inner.read(buf)?;
+            if bytes_read == 0 {
+                if !self.to_end && (self.inner.limit() > 0) && !buf.is_empty() {
+                    return Err(region_eof_error());
+                }
+
+                return Ok(0);
+            }
+
+            let start = self.pos;
+            let end = start + bytes_read as u64;
+            self.pos = end;
+
+            // Move the bytes which are not excluded to the front of the buffer
+            let mut kept: usize = 0;
+            let mut cur = start;
+
+            for (ex_offset, ex_length) in &self.excludes {
+                let ex_start = std::cmp::max(*ex_offset, cur);
+                let ex_end = std::cmp::min(ex_offset.saturating_add(*ex_length), end);
+
+                if ex_start >= ex_end {
+                    continue;
+                }
+
+                let keep_len = (ex_start - cur) as usize;
+                let src = (cur - start) as usize;
+                buf.copy_within(src..src + keep_len, kept);
+                kept += keep_len;
+                cur = ex_end;
+            }
+
+            let src = (cur - start) as usize;
+            buf.copy_within(src..bytes_read, kept);
+            kept += bytes_read - src;
+
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
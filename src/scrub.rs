@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::escape::{self, ESCAPE_MARKER};
+use crate::reffile::RefEntry;
+
+// The state file contains one line of the form <seconds since the epoch> <file name> for
+// each file which has already been verified. File names are escaped in the same way as
+// in reference data.
+pub struct ScrubState {
+    last_checked: HashMap<PathBuf, u64>
+}
+
+impl ScrubState {
+    pub fn new() -> ScrubState {
+        return ScrubState { last_checked: HashMap::new() };
+    }
+
+    pub fn load<R: BufRead>(r: R) -> io::Result<ScrubState> {
+        let mut state = ScrubState::new();
+
+        for (line_no, line) in r.lines().enumerate() {
+            let line = line?;
+            let malformed = || io::Error::new(io::ErrorKind::InvalidData, format!("State file line {} has wrong format", line_no + 1));
+
+            let escaped = line.starts_with(ESCAPE_MARKER);
+            let line = if escaped { &line[1..] } else { &line[..] };
+            let (time, name) = line.split_once(' ').ok_or_else(malformed)?;
+            let time: u64 = time.parse().map_err(|_| malformed())?;
+
+            let file_name = match escaped {
+                true => escape::unescape_name(name).ok_or_else(malformed)?,
+                false => PathBuf::from(name)
+            };
+
+            state.last_checked.insert(file_name, time);
+        }
+
+        return Ok(state);
+    }
+
+    // Only the given files are saved, so that files which have been removed from the
+    // reference data do not stay in the state file forever
+    pub fn save<W: Write>(&self, w: &mut W, file_names: &[PathBuf]) -> io::Result<()> {
+        for file_name in file_names {
+            if let Some(time) = self.last_checked.get(file_name) {
+                let (name, needs_escape) = escape::escape_name(file_name);
+                let marker = if needs_escape { String::from(ESCAPE_MARKER) } else { String::new() };
+
+                writeln!(w, "{}{} {}", marker, time, name)?;
+            }
+        }
+
+        return w.flush();
+    }
+
+    // The new state replaces the old one only after it has been written to disk completely,
+    // so that an interrupted scrub never leaves a truncated state file behind
+    pub fn save_file(&self, state_file: &Path, file_names: &[PathBuf]) -> io::Result<()> {
+        let mut tmp_name = state_file.to_path_buf().into_os_string();
+        tmp_name.push(".tmp");
+        let tmp_name = PathBuf::from(tmp_name);
+
+        let mut state_out = File::create(&tmp_name)?;
+        self.save(&mut io::BufWriter::new(&mut state_out), file_names)?;
+        state_out.sync_all()?;
+
+        return fs::rename(&tmp_name, state_file);
+    }
+
+    // Returns 0 for files which have never been verified
+    pub fn last_checked(&self, file_name: &Path) -> u64 {
+        return *self.last_checked.get(file_name).unwrap_or(&0);
+    }
+
+    pub fn mark_checked(&mut self, file_name: &Path, time: u64) {
+        self.last_checked.insert(file_name.to_path_buf(), time);
+    }
+
+    // Sorts the entries so that the least recently verified files come first. Entries
+    // verified at the same time keep the order of the reference data.
+    pub fn order(&self, entries: &mut [RefEntry]) {
+        entries.sort_by_key(|e| self.last_checked(&e.file_name));
+    }
+}
+
+// A scrub stops as soon as one of the limits is reached. The limits are checked before a
+// file is verified, so the last file is always verified completely and may exceed them.
+pub struct Budget {
+    pub time: Option<Duration>,
+    pub bytes: Option<u64>
+}
+
+impl Budget {
+    pub fn is_used(&self, elapsed: Duration, bytes_read: u64) -> bool {
+        let time_used = self.time.is_some_and(|t| elapsed >= t);
+        let bytes_used = self.bytes.is_some_and(|b| bytes_read >= b);
+
+        return time_used || bytes_used;
+    }
+}
+
+// Parses a duration given in seconds. The suffixes s, m and h are allowed.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let (digits, factor) = match duration.chars().last()? {
+        's' => (&duration[..duration.len() - 1], 1u64),
+        'm' => (&duration[..duration.len() - 1], 60u64),
+        'h' => (&duration[..duration.len() - 1], 3600u64),
+        _ => (duration, 1u64)
+    };
+
+    let secs = digits.parse::<u64>().ok()?.checked_mul(factor)?;
+
+    return if secs == 0 { None } else { Some(Duration::from_secs(secs)) };
+}
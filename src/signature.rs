@@ -0,0 +1,235 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use crate::escape::display_name;
+
+pub const SIG_TYPE_SIGNIFY: &str = "signify";
+pub const SIG_TYPE_MINISIGN: &str = "minisign";
+pub const SIG_TYPE_PGP: &str = "pgp";
+
+const SIGNIFY_ALGO: &[u8] = b"Ed";
+const SIGNIFY_KEY_NUM_LEN: usize = 8;
+const SIGNIFY_PUB_KEY_LEN: usize = 2 + SIGNIFY_KEY_NUM_LEN + 32;
+const SIGNIFY_SIG_LEN: usize = 2 + SIGNIFY_KEY_NUM_LEN + 64;
+const UNTRUSTED_COMMENT: &str = "untrusted comment: ";
+
+pub enum SignatureError {
+    FormatError(String),
+    KeyMismatch,
+    VerifyFail,
+    NoMessage,
+    Unsupported(String),
+    ToolError(String),
+    ReadError(String)
+}
+
+impl SignatureError {
+    pub fn message(&self) -> String {
+        match self {
+            SignatureError::FormatError(what) => format!("Malformed {}", what),
+            SignatureError::KeyMismatch => "Signature was not created with the given public key".to_string(),
+            SignatureError::VerifyFail => "Signature verification failed".to_string(),
+            SignatureError::NoMessage => "Signature does not embed a message and no input was given".to_string(),
+            SignatureError::Unsupported(what) => format!("{} is not supported", what),
+            SignatureError::ToolError(tool) => format!("Unable to run '{}'", tool),
+            SignatureError::ReadError(file_name) => format!("Unable to read '{}'", file_name)
+        }
+    }
+}
+
+pub trait SignatureVerifier {
+    // Verifies sig_data and returns the signed message. If message is None the message
+    // has to be embedded in the signature file.
+    fn verify(&self, sig_data: &[u8], message: Option<Vec<u8>>) -> Result<Vec<u8>, SignatureError>;
+}
+
+// Splits off the first line, which has to be an untrusted comment, and the base64 line
+// following it. Returns the decoded second line and the remaining data.
+fn split_signify_lines<'a>(data: &'a [u8], what: &str) -> Result<(Vec<u8>, &'a [u8]), SignatureError> {
+    let format_err = || SignatureError::FormatError(what.to_string());
+
+    let comment_end = data.iter().position(|&b| b == b'\n').ok_or_else(format_err)?;
+    if !data.starts_with(UNTRUSTED_COMMENT.as_bytes()) {
+        return Err(format_err());
+    }
+
+    let rest = &data[comment_end + 1..];
+    let b64_end = rest.iter().position(|&b| b == b'\n').ok_or_else(format_err)?;
+    let b64_line = std::str::from_utf8(&rest[..b64_end]).map_err(|_| format_err())?;
+    let decoded = STANDARD.decode(b64_line.trim()).map_err(|_| format_err())?;
+
+    return Ok((decoded, &rest[b64_end + 1..]));
+}
+
+pub struct SignifyVerifier {
+    key_num: [u8; SIGNIFY_KEY_NUM_LEN],
+    key: VerifyingKey
+}
+
+impl SignifyVerifier {
+    pub fn new(pub_key_data: &[u8]) -> Result<SignifyVerifier, SignatureError> {
+        let (raw_key, _) = split_signify_lines(pub_key_data, "signify public key")?;
+
+        if (raw_key.len() != SIGNIFY_PUB_KEY_LEN) || !raw_key.starts_with(SIGNIFY_ALGO) {
+            return Err(SignatureError::FormatError("signify public key".to_string()));
+        }
+
+        let mut key_num = [0u8; SIGNIFY_KEY_NUM_LEN];
+        key_num.copy_from_slice(&raw_key[2..2 + SIGNIFY_KEY_NUM_LEN]);
+        let mut key_bytes = [0u8; 32];
+        key_bytes.copy_from_slice(&raw_key[2 + SIGNIFY_KEY_NUM_LEN..]);
+
+        let key = match VerifyingKey::from_bytes(&key_bytes) {
+            Ok(k) => k,
+            Err(_) => return Err(SignatureError::FormatError("signify public key".to_string()))
+        };
+
+        return Ok(SignifyVerifier { key_num, key });
+    }
+}
+
+impl SignatureVerifier for SignifyVerifier {
+    fn verify(&self, sig_data: &[u8], message: Option<Vec<u8>>) -> Result<Vec<u8>, SignatureError> {
+        let (raw_sig, embedded) = split_signify_lines(sig_data, "signify signature")?;
+
+        if (raw_sig.len() != SIGNIFY_SIG_LEN) || !raw_sig.starts_with(SIGNIFY_ALGO) {
+            return Err(SignatureError::FormatError("signify signature".to_string()));
+        }
+
+        if raw_sig[2..2 + SIGNIFY_KEY_NUM_LEN] != self.key_num {
+            return Err(SignatureError::KeyMismatch);
+        }
+
+        let sig = Signature::from_slice(&raw_sig[2 + SIGNIFY_KEY_NUM_LEN..]).map_err(|_| SignatureError::VerifyFail)?;
+
+        // signify -e appends the signed message to the signature file
+        let msg = match message {
+            Some(m) => m,
+            None if !embedded.is_empty() => embedded.to_vec(),
+            None => return Err(SignatureError::NoMessage)
+        };
+
+        if self.key.verify_strict(&msg, &sig).is_err() {
+            return Err(SignatureError::VerifyFail);
+        }
+
+        return Ok(msg);
+    }
+}
+
+pub struct MinisignVerifier {
+    key: minisign_verify::PublicKey
+}
+
+impl MinisignVerifier {
+    pub fn new(pub_key_data: &[u8]) -> Result<MinisignVerifier, SignatureError> {
+        let format_err = || SignatureError::FormatError("minisign public key".to_string());
+        let key_str = std::str::from_utf8(pub_key_data).map_err(|_| format_err())?;
+
+        // Accept a complete minisign.pub file as well as the bare base64 key
+        let key = if key_str.starts_with(UNTRUSTED_COMMENT) {
+            minisign_verify::PublicKey::decode(key_str)
+        } else {
+            minisign_verify::PublicKey::from_base64(key_str.trim())
+        };
+
+        return match key {
+            Ok(k) => Ok(MinisignVerifier { key: k }),
+            Err(_) => Err(format_err())
+        };
+    }
+}
+
+impl SignatureVerifier for MinisignVerifier {
+    fn verify(&self, sig_data: &[u8], message: Option<Vec<u8>>) -> Result<Vec<u8>, SignatureError> {
+        let format_err = || SignatureError::FormatError("minisign signature".to_string());
+        let sig_str = std::str::from_utf8(sig_data).map_err(|_| format_err())?;
+        let sig = minisign_verify::Signature::decode(sig_str).map_err(|_| format_err())?;
+
+        let msg = match message {
+            Some(m) => m,
+            None => return Err(SignatureError::NoMessage)
+        };
+
+        return match self.key.verify(&msg, &sig, true) {
+            Ok(_) => Ok(msg),
+            Err(minisign_verify::Error::UnexpectedKeyId) => Err(SignatureError::KeyMismatch),
+            Err(_) => Err(SignatureError::VerifyFail)
+        };
+    }
+}
+
+const GPGV_BINARY: &str = "gpgv";
+
+// Checks PGP clearsigned reference data by handing it to gpgv. The keyring is
+// a local file containing the trusted public keys, as for gpgv --keyring.
+pub struct PgpVerifier {
+    keyring: PathBuf
+}
+
+impl PgpVerifier {
+    pub fn new(keyring: &Path) -> Result<PgpVerifier, SignatureError> {
+        // gpgv looks up relative keyring names in its home directory
+        return match std::fs::canonicalize(keyring) {
+            Ok(p) => Ok(PgpVerifier { keyring: p }),
+            Err(_) => Err(SignatureError::FormatError(format!("keyring file name '{}'", display_name(keyring))))
+        };
+    }
+}
+
+impl SignatureVerifier for PgpVerifier {
+    fn verify(&self, sig_data: &[u8], message: Option<Vec<u8>>) -> Result<Vec<u8>, SignatureError> {
+        if message.is_some() {
+            return Err(SignatureError::Unsupported("A detached PGP signature".to_string()));
+        }
+
+        // Only the signed text written by gpgv is used. Data outside of the envelope is
+        // not covered by the signature.
+        let mut child = Command::new(GPGV_BINARY)
+            .arg("--keyring")
+            .arg(&self.keyring)
+            .arg("--output")
+            .arg("-")
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|_| SignatureError::ToolError(GPGV_BINARY.to_string()))?;
+
+        // stdin is written by another thread, so that gpgv can not block on a full stdout pipe
+        let mut stdin = child.stdin.take().unwrap();
+        let input = sig_data.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+        let mut signed_text: Vec<u8> = Vec::new();
+        let read_res = child.stdout.take().unwrap().read_to_end(&mut signed_text);
+        let write_res = writer.join().map_err(|_| SignatureError::ToolError(GPGV_BINARY.to_string()))?;
+        let status = child.wait().map_err(|_| SignatureError::ToolError(GPGV_BINARY.to_string()))?;
+
+        if write_res.is_err() || read_res.is_err() || !status.success() {
+            return Err(SignatureError::VerifyFail);
+        }
+
+        return Ok(signed_text);
+    }
+}
+
+pub fn make_verifier(sig_type: &str, key_file: &Path) -> Result<Box<dyn SignatureVerifier>, SignatureError> {
+    if sig_type == SIG_TYPE_PGP {
+        return Ok(Box::new(PgpVerifier::new(key_file)?));
+    }
+
+    let key_data = match std::fs::read(key_file) {
+        Ok(d) => d,
+        Err(_) => return Err(SignatureError::ReadError(display_name(key_file)))
+    };
+
+    return match sig_type {
+        SIG_TYPE_MINISIGN => Ok(Box::new(MinisignVerifier::new(&key_data)?)),
+        _ => Ok(Box::new(SignifyVerifier::new(&key_data)?))
+    };
+}
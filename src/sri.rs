@@ -0,0 +1,208 @@
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+use crate::encoding::Encoding;
+use crate::escape::{path_from_bytes, ESCAPE_MARKER};
+use crate::formatter::{format_escaped, parse_name, HashLineFormatter, ParseError};
+use crate::hasher::from_hex_string;
+
+// Subresource Integrity (https://www.w3.org/TR/SRI/) describes a hash as <algorithm>-<base64>,
+// where the algorithm is one of the following. They are ordered from the weakest to the
+// strongest.
+pub const SRI_ALGOS: [&str; 3] = ["sha256", "sha384", "sha512"];
+
+// A token of an integrity string like sha384-<base64>
+#[derive(Clone, PartialEq, Debug)]
+pub struct SriToken {
+    pub algo: String,
+    pub hash: String
+}
+
+// Splits an integrity string into its tokens. Tokens with an unknown algorithm are ignored
+// as required by the specification. Options following a '?' are dropped.
+pub fn parse_integrity(integrity: &str) -> Vec<SriToken> {
+    let mut res = Vec::new();
+
+    for token in integrity.split_ascii_whitespace() {
+        let token = token.split('?').next().unwrap_or("");
+
+        if let Some((algo, hash)) = token.split_once('-') {
+            if SRI_ALGOS.contains(&algo) && !hash.is_empty() {
+                res.push(SriToken { algo: algo.to_string(), hash: hash.to_string() });
+            }
+        }
+    }
+
+    return res;
+}
+
+// Returns the tokens of the strongest algorithm. A resource matches if it matches any of them.
+pub fn strongest_tokens(tokens: &[SriToken]) -> Vec<SriToken> {
+    let strength = |t: &SriToken| SRI_ALGOS.iter().position(|a| *a == t.algo).unwrap_or(0);
+
+    return match tokens.iter().map(strength).max() {
+        Some(max) => tokens.iter().filter(|t| strength(t) == max).cloned().collect(),
+        None => Vec::new()
+    };
+}
+
+// Reference data lines have the form <integrity string>  <file name>. The formatter is
+// created with the SRI names of the algorithms and the length of their hashes in hex. When
+// formatting the hex hashes of all algorithms are expected one after another. Parsing
+// returns the hash of the first algorithm.
+pub struct SriFormatter {
+    algos: Vec<(String, usize)>,
+    exp: Regex
+}
+
+impl SriFormatter {
+    pub fn new(algos: Vec<(String, usize)>) -> SriFormatter {
+        return SriFormatter {
+            algos,
+            exp: Regex::new(r"^(\S+(?: \S+)*)  (.*)$").unwrap()
+        }
+    }
+}
+
+impl HashLineFormatter for SriFormatter {
+    fn format(&self, hash: &str, file_name: &Path) -> String {
+        let mut tokens: Vec<String> = Vec::new();
+        let mut pos: usize = 0;
+
+        for (algo, hex_len) in &self.algos {
+            let part = hash.get(pos..pos + hex_len).unwrap_or("");
+            let encoded = match from_hex_string(part) {
+                Some(data) => Encoding::Base64.encode(&data),
+                None => part.to_string()
+            };
+
+            tokens.push(format!("{}-{}", algo, encoded));
+            pos += hex_len;
+        }
+
+        return format_escaped(file_name, |name| format!("{}  {}", tokens.join(" "), name));
+    }
+
+    fn parse(&self, hash_line: &str) -> Result<(PathBuf, String), ParseError> {
+        let escaped = hash_line.starts_with(ESCAPE_MARKER);
+        let line = if escaped { &hash_line[1..] } else { hash_line };
+
+        let groups = match self.exp.captures(line) {
+            Some(g) => g,
+            None => return Err(ParseError::FormatError(hash_line.to_string()))
+        };
+
+        let algo = match self.algos.first() {
+            Some((a, _)) => a,
+            None => return Err(ParseError::FormatError(hash_line.to_string()))
+        };
+
+        return match parse_integrity(&groups[1]).into_iter().find(|t| t.algo == *algo) {
+            Some(token) => Ok((parse_name(&groups[2], escaped, hash_line)?, token.hash)),
+            None => Err(ParseError::FormatError(hash_line.to_string()))
+        };
+    }
+}
+
+// A script or stylesheet referenced by an HTML file together with its integrity attribute
+#[derive(PartialEq, Debug)]
+pub struct SriResource {
+    pub url: String,
+    pub integrity: String
+}
+
+// Finds the script and link elements of an HTML document which have an integrity attribute
+pub fn find_resources(html: &str) -> Vec<SriResource> {
+    let tag_exp = Regex::new(r"(?is)<(script|link)\b([^>]*)>").unwrap();
+    // The attribute name has to start after whitespace, so that e.g. data-src is not used
+    let attr_exp = Regex::new(r#"(?is)(?:^|\s)(integrity|src|href)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+    let mut res = Vec::new();
+
+    for tag in tag_exp.captures_iter(html) {
+        let mut url: Option<String> = None;
+        let mut integrity: Option<String> = None;
+
+        for attr in attr_exp.captures_iter(&tag[2]) {
+            let value = attr.get(2).or(attr.get(3)).or(attr.get(4)).map_or("", |v| v.as_str()).to_string();
+
+            match attr[1].to_ascii_lowercase().as_str() {
+                "integrity" => integrity = Some(value),
+                _ => url = Some(value)
+            }
+        }
+
+        if let (Some(url), Some(integrity)) = (url, integrity) {
+            res.push(SriResource { url, integrity });
+        }
+    }
+
+    return res;
+}
+
+// Replaces %XX sequences by the bytes they stand for. Other % characters are kept.
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut res: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let decoded = match bytes[i] {
+            b'%' => bytes.get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None
+        };
+
+        match decoded {
+            Some(b) => {
+                res.push(b);
+                i += 3;
+            },
+            None => {
+                res.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    return res;
+}
+
+// Removes . and resolves .. components without looking at the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+
+    for c in path.components() {
+        match c {
+            Component::CurDir => {},
+            Component::ParentDir if matches!(res.components().next_back(), Some(Component::Normal(_))) => {
+                res.pop();
+            },
+            c => res.push(c)
+        }
+    }
+
+    return res;
+}
+
+// Returns the local file a URL refers to. Relative URLs are resolved against the directory
+// of the HTML file and absolute paths against the root directory. URLs with a scheme or a
+// host and files outside of the root directory can not be checked.
+pub fn local_path(url: &str, html_dir: &Path, root: &Path) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next().unwrap_or("");
+
+    if path.is_empty() || path.starts_with("//") || path.contains("://") || path.starts_with("data:") {
+        return None;
+    }
+
+    let decoded = path_from_bytes(percent_decode(path));
+    let file_name = match decoded.strip_prefix("/") {
+        Ok(p) => normalize(&root.join(p)),
+        Err(_) => normalize(&html_dir.join(&decoded))
+    };
+
+    if !file_name.starts_with(normalize(root)) {
+        return None;
+    }
+
+    return Some(file_name);
+}
@@ -7,18 +7,67 @@ use crate::formatter::*;
 #[cfg(test)]
 use std::rc::Rc;
 #[cfg(test)]
+use std::io::Read;
+#[cfg(test)]
+use std::path::Path;
+#[cfg(test)]
 use crate::reffile::*;
 #[cfg(test)]
+use crate::signature::*;
+#[cfg(test)]
+use crate::chunked::*;
+#[cfg(test)]
+use crate::merkle::*;
+#[cfg(test)]
+use crate::dirhash::*;
+#[cfg(test)]
+use crate::cdc::*;
+#[cfg(test)]
+use crate::region::*;
+#[cfg(test)]
+use crate::escape::*;
+#[cfg(test)]
+use crate::encoding::*;
+#[cfg(test)]
+use crate::compress::*;
+#[cfg(test)]
+use crate::archive::*;
+#[cfg(test)]
 use crate::*;
 
 
 #[test]
 fn try_error_messages() {
     assert_eq!(HashError::GenericError.message(), "Operation failed".to_string());
-    assert_eq!(HashError::FileOpenError("test_file.txt".to_string()).message(), "Error opening file 'test_file.txt'");
+    let not_found = || std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
+    assert_eq!(HashError::FileOpenError("test_file.txt".to_string(), not_found()).message(), "Error opening file 'test_file.txt': not found");
+    assert_eq!(HashError::ReadError(None, 4096, not_found()).in_file(Path::new("test_file.txt"), 10).message(), "Unable to read file 'test_file.txt' at offset 4106: not found");
     assert_eq!(HashError::HashVerifyFail("test_file.txt".to_string()).message(), "Hash verification for file 'test_file.txt' failed");
     assert_eq!(HashError::Ok.message(), "OK");
     assert_eq!(HashError::HashDifferent.message(), "Hashes different");
+
+    let err = HashError::FileOpenError("test_file.txt".to_string(), not_found());
+    assert_eq!(std::error::Error::source(&err).unwrap().to_string(), "not found");
+    assert_eq!(error_status(&err), PROG_RETURN_MISSING);
+    assert_eq!(error_status(&HashError::HashVerifyFail("test_file.txt".to_string())), PROG_RETURN_MISMATCH);
+    assert_eq!(worse_status(PROG_RETURN_MALFORMED, PROG_RETURN_MISSING), PROG_RETURN_MALFORMED);
+    assert_eq!(worse_status(PROG_RETURN_OK, PROG_RETURN_MISMATCH), PROG_RETURN_MISMATCH);
+}
+
+#[test]
+fn hash_compare_test() {
+    let mut h = Hasher::new(ALGO_SHA256, Box::new(Sha256::new()));
+    let upper = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD".to_string();
+
+    assert!(matches!(h.verify_data(&mut "abc".as_bytes(), &upper), HashError::Ok));
+    assert!(matches!(h.verify_data(&mut "abd".as_bytes(), &upper), HashError::HashDifferent));
+
+    let short = h.verify_data(&mut "abc".as_bytes(), &upper[..62]);
+    assert_eq!(short.message(), format!("Reference hash '{}' is not a SHA256 hash in a supported encoding", &upper[..62]));
+    assert!(matches!(compare_hashes(ALGO_SHA256, "abc", "abcd"), HashError::WrongHashLength(_, _)));
+
+    assert_eq!(from_hex_string("0aFf"), Some(vec![0x0a, 0xff]));
+    assert_eq!(from_hex_string("0g"), None);
 }
 
 #[test]
@@ -35,7 +84,7 @@ fn test_sha256_hash_reference_values() {
 
     ref_data = "abc".as_bytes();
 
-    let mut h_res = h.verify_data(&mut ref_data, &"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string());
+    let mut h_res = h.verify_data(&mut ref_data, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
     match h_res {
         HashError::Ok => {},
         _ => {
@@ -56,7 +105,7 @@ fn test_sha256_hash_reference_values() {
     
     a_million_a = vec![97; 1000000];
 
-    h_res = h.verify_data(&mut a_million_a.as_slice(), &"cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0".to_string());
+    h_res = h.verify_data(&mut a_million_a.as_slice(), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
     match h_res {
         HashError::Ok => {},
         _ => {
@@ -81,7 +130,7 @@ fn simple_format_test() {
     };
 
     assert_eq!(data.1, "abcdef0123456789");
-    assert_eq!(data.0, "data.txt");
+    assert_eq!(data.0, Path::new("data.txt"));
 
     test_line = String::from("abcdef0123456789                data.txt");
 
@@ -94,28 +143,25 @@ fn simple_format_test() {
     };
 
     assert_eq!(data.1, "abcdef0123456789");
-    assert_eq!(data.0, "data.txt");    
+    assert_eq!(data.0, Path::new("data.txt"));    
 
     test_line = String::from("abcdef012345678 data.txt");
 
-    match formatter.parse(&test_line) {
-        Ok(_) => panic!("SimpleFormatter test failed. The line was mismatched"),
-        Err(_) => {}
-    };    
+    assert!(formatter.parse(&test_line).is_err(), "SimpleFormatter test failed. The line was mismatched");
 
     test_line = String::from("abcdef012345678G  data.txt");
 
-    match formatter.parse(&test_line) {
-        Ok(_) => panic!("SimpleFormatter test failed. The line was mismatched"),
-        Err(_) => {}
-    }; 
+    // G is a valid base64 or base58 digit, so the line is only rejected when the hash is compared
+    let data = formatter.parse(&test_line).ok().unwrap();
+    assert!(matches!(compare_hashes(ALGO_SHA256, &data.1, "abcdef0123456789"), HashError::WrongHashLength(_, _)));
+
+    test_line = String::from("abcdef012345678!  data.txt");
+
+    assert!(formatter.parse(&test_line).is_err(), "SimpleFormatter test failed. The line was mismatched");
 
     test_line = String::from("abcdef01234567");
 
-    match formatter.parse(&test_line) {
-        Ok(_) => panic!("SimpleFormatter test failed. The line was mismatched"),
-        Err(_) => {}
-    }; 
+    assert!(formatter.parse(&test_line).is_err(), "SimpleFormatter test failed. The line was mismatched");
 }
 
 #[test]
@@ -132,7 +178,7 @@ fn bsd_parser_test() {
         }
     }; 
     
-    assert_eq!(data.0, "data.txt");
+    assert_eq!(data.0, Path::new("data.txt"));
     assert_eq!(data.1, "abcdef0123456789");
 
     test_line = String::from("SHA256 ((data .txt)) = abcdef0123456789");
@@ -145,7 +191,7 @@ fn bsd_parser_test() {
         }
     }; 
     
-    assert_eq!(data.0, "(data .txt)");
+    assert_eq!(data.0, Path::new("(data .txt)"));
     assert_eq!(data.1, "abcdef0123456789");    
 }
 
@@ -155,13 +201,22 @@ fn iterator_test() {
     
     let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
     let ref_data = RefFile::new(data.as_bytes(), &f);
-    let res: Vec<(String, String)> = ref_data.into_iter().collect();
+    let res: Vec<RefEntry> = ref_data.into_iter().map(|x| x.ok().unwrap()).collect();
 
     assert_eq!(res.len(), 2);
-    assert_eq!(res[0].1, "111111");
-    assert_eq!(res[0].0, "dateia");
-    assert_eq!(res[1].1, "222222");
-    assert_eq!(res[1].0, "dateib");
+    assert_eq!(res[0].hash, "111111");
+    assert_eq!(res[0].file_name, Path::new("dateia"));
+    assert_eq!(res[1].hash, "222222");
+    assert_eq!(res[1].file_name, Path::new("dateib"));
+
+    // Regions are carried through, but can not be combined with chunk hashes
+    let data = String::from("111111  dateia\nREGION 10 20\n222222  dateib\nCHUNK 1024 0 aaaa\nREGION 10 20\n333333  dateic\n");
+    let entries: Vec<Result<ChunkedEntry, RefFileError>> = ChunkedRefFile::new(data.as_bytes(), &f).collect();
+    assert_eq!(entries.len(), 3);
+    let first = entries[0].as_ref().ok().unwrap();
+    assert_eq!((first.region.offset, first.region.length), (10, Some(20)));
+    assert!(matches!(entries[1], Err(RefFileError::Malformed(5, _))));
+    assert!(entries[2].as_ref().ok().unwrap().region.is_whole());
 }
 
 #[test]
@@ -171,11 +226,971 @@ fn iterator_bsd_test() {
     
     let f: Rc<dyn HashLineFormatter> = Rc::new(BsdFormatter::new(&String::from(algo_name)));
     let ref_data = RefFile::new(data.as_bytes(), &f);
-    let res: Vec<(String, String)> = ref_data.into_iter().collect();
+    let res: Vec<RefEntry> = ref_data.into_iter().map(|x| x.ok().unwrap()).collect();
 
     assert_eq!(res.len(), 2);
-    assert_eq!(res[0].1, "111111");
-    assert_eq!(res[0].0, "dateia");
-    assert_eq!(res[1].1, "222222");
-    assert_eq!(res[1].0, "dateib");
-}
\ No newline at end of file
+    assert_eq!(res[0].hash, "111111");
+    assert_eq!(res[0].file_name, Path::new("dateia"));
+    assert_eq!(res[1].hash, "222222");
+    assert_eq!(res[1].file_name, Path::new("dateib"));
+}
+
+#[test]
+fn malformed_line_test() {
+    let data = String::from("111111  dateia\nkaputt\n222222  dateib\n333333  dateic\nREGION x -\n444444  dateid\n");
+
+    let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
+    let ref_data = RefFile::new(data.as_bytes(), &f);
+    let res: Vec<Result<RefEntry, RefFileError>> = ref_data.into_iter().collect();
+
+    assert_eq!(res.len(), 5);
+    assert_eq!(res[0].as_ref().ok().unwrap().line_no, 1);
+    assert!(matches!(res[1], Err(RefFileError::Malformed(2, _))));
+    assert_eq!(res[2].as_ref().ok().unwrap().line_no, 3);
+    assert_eq!(res[2].as_ref().ok().unwrap().file_name, Path::new("dateib"));
+    assert!(matches!(res[3], Err(RefFileError::Malformed(5, _))));
+    assert_eq!(res[4].as_ref().ok().unwrap().file_name, Path::new("dateid"));
+    assert_eq!(res[1].as_ref().err().unwrap().message(), "Line 2: Input line 'kaputt' has wrong format");
+}
+
+#[cfg(test)]
+fn make_signify_files(message: &[u8], embed: bool) -> (Vec<u8>, Vec<u8>) {
+    use base64::Engine;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let key_num = [1u8, 2, 3, 4, 5, 6, 7, 8];
+
+    let mut raw_key: Vec<u8> = b"Ed".to_vec();
+    raw_key.extend_from_slice(&key_num);
+    raw_key.extend_from_slice(signing_key.verifying_key().as_bytes());
+
+    let mut raw_sig: Vec<u8> = b"Ed".to_vec();
+    raw_sig.extend_from_slice(&key_num);
+    raw_sig.extend_from_slice(&signing_key.sign(message).to_bytes());
+
+    let pub_key = format!("untrusted comment: signify public key\n{}\n", b64.encode(raw_key)).into_bytes();
+    let mut sig = format!("untrusted comment: verify with test.pub\n{}\n", b64.encode(raw_sig)).into_bytes();
+
+    if embed {
+        sig.extend_from_slice(message);
+    }
+
+    return (pub_key, sig);
+}
+
+#[test]
+fn signify_test() {
+    let message = b"SHA256 (dateia) = 111111\n".to_vec();
+    let (pub_key, sig) = make_signify_files(&message, true);
+    let verifier = SignifyVerifier::new(&pub_key).ok().unwrap();
+
+    match verifier.verify(&sig, None) {
+        Ok(m) => assert_eq!(m, message),
+        Err(e) => panic!("{}", e.message())
+    };
+
+    let (_, detached_sig) = make_signify_files(&message, false);
+
+    match verifier.verify(&detached_sig, None) {
+        Err(SignatureError::NoMessage) => {},
+        _ => panic!("Missing message not detected")
+    };
+
+    match verifier.verify(&detached_sig, Some(b"SHA256 (dateia) = 111112\n".to_vec())) {
+        Err(SignatureError::VerifyFail) => {},
+        _ => panic!("Wrong signature was accepted")
+    };
+}
+
+#[test]
+fn minisign_test() {
+    let pub_key = "untrusted comment: minisign public key E7620F1842B4E81F\nRWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3\n";
+    let sig = "untrusted comment: signature from minisign secret key\n\
+        RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=\n\
+        trusted comment: timestamp:1555779966\tfile:test\n\
+        QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==\n";
+    let verifier = MinisignVerifier::new(pub_key.as_bytes()).ok().unwrap();
+
+    match verifier.verify(sig.as_bytes(), Some(b"test".to_vec())) {
+        Ok(_) => {},
+        Err(e) => panic!("{}", e.message())
+    };
+
+    match verifier.verify(sig.as_bytes(), Some(b"Test".to_vec())) {
+        Err(SignatureError::VerifyFail) => {},
+        _ => panic!("Wrong signature was accepted")
+    };
+}
+
+#[test]
+fn clearsign_test() {
+    let data = String::from("-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA256\n\n\
+        111111  dateia\n\
+        - -----BEGIN PGP-like name\n\
+        222222  dateib\n\
+        -----BEGIN PGP SIGNATURE-----\n\n\
+        iQIzBAEBCAAdFiEE\n=abcd\n\
+        -----END PGP SIGNATURE-----\n");
+
+    let lines: Vec<String> = ArmorLines::new(data.as_bytes()).map(|x| x.unwrap().1).collect();
+    assert_eq!(lines, vec!["111111  dateia", "-----BEGIN PGP-like name", "222222  dateib"]);
+
+    let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
+    let ref_data = RefFile::new("-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA256\n\n111111  dateia\n".as_bytes(), &f);
+    let res: Vec<RefEntry> = ref_data.into_iter().map(|x| x.ok().unwrap()).collect();
+
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].file_name, Path::new("dateia"));
+}
+
+#[test]
+fn chunked_hash_test() {
+    let mut h = Hasher::new("SHA-256", Box::new(Sha256::new()));
+    let data: Vec<u8> = (0..10000u32).map(|x| (x % 251) as u8).collect();
+
+    let (whole, chunks) = match h.hash_data_chunked(&mut data.as_slice(), 3000) {
+        Ok(res) => res,
+        Err(e) => panic!("{}", e.message())
+    };
+
+    assert_eq!(Ok(whole), h.hash_data(&mut data.as_slice()).map_err(|e| e.message()));
+    assert_eq!(chunks.len(), 4);
+
+    for (i, chunk) in data.chunks(3000).enumerate() {
+        assert_eq!(Ok(chunks[i].clone()), h.hash_data(&mut &chunk[..]).map_err(|e| e.message()));
+    }
+
+    let mut changed = chunks.clone();
+    changed[1] = String::from("00");
+    changed[2] = String::from("00");
+    changed.pop();
+
+    let problems: Vec<String> = find_bad_ranges(3000, 9500, &chunks, &changed).iter().map(|p| p.message()).collect();
+    assert_eq!(problems, vec!["bytes 3000-8999 differ", "data from byte 9500 on is missing"]);
+
+    assert_eq!(parse_size("4K"), Some(4096));
+    assert_eq!(parse_size("1M"), Some(1048576));
+    assert_eq!(parse_size("0"), None);
+    assert_eq!(parse_size("xyz"), None);
+}
+
+#[test]
+fn chunked_iterator_test() {
+    let data = String::from("111111  dateia\nCHUNK 1024 0 aaaa\nCHUNK 1024 1 bbbb\n222222  dateib\n");
+    let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
+
+    let entries: Vec<ChunkedEntry> = ChunkedRefFile::new(data.as_bytes(), &f).map(|x| x.ok().unwrap()).collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].file_name, Path::new("dateia"));
+    assert_eq!(entries[0].chunk_size, 1024);
+    assert_eq!(entries[0].chunk_hashes, vec!["aaaa", "bbbb"]);
+    assert_eq!(entries[1].hash, "222222");
+    assert!(entries[1].chunk_hashes.is_empty());
+
+    let ref_data = RefFile::new(data.as_bytes(), &f);
+    let res: Vec<RefEntry> = ref_data.into_iter().map(|x| x.ok().unwrap()).collect();
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[1].file_name, Path::new("dateib"));
+}
+
+// Recomputes the root of a hash tree from a leaf and its inclusion proof
+#[cfg(test)]
+fn verify_proof(leaf: &[u8], proof: &[ProofStep], root: &[u8], digest: &mut dyn digest::DynDigest) -> bool {
+    let mut current: Box<[u8]> = leaf.into();
+
+    for step in proof {
+        let (left, right) = match step {
+            ProofStep::Left(sibling) => (sibling.clone(), current),
+            ProofStep::Right(sibling) => (current, sibling.clone())
+        };
+
+        digest.update(&left);
+        digest.update(&right);
+        current = digest.finalize_reset();
+    }
+
+    return &current[..] == root;
+}
+
+#[test]
+fn merkle_tree_test() {
+    let mut h = Hasher::new("SHA-256", Box::new(Sha256::new()));
+    let mut tree_h = Hasher::new_tree("SHA-256", Box::new(Sha256::new()), 1000);
+    let data: Vec<u8> = (0..4500u32).map(|x| (x % 253) as u8).collect();
+
+    let leaves: Vec<Vec<u8>> = data.chunks(1000).map(|c| Sha256::digest(c).to_vec()).collect();
+    let h01 = Sha256::digest([&leaves[0][..], &leaves[1][..]].concat());
+    let h23 = Sha256::digest([&leaves[2][..], &leaves[3][..]].concat());
+    let h0123 = Sha256::digest([&h01[..], &h23[..]].concat());
+    let root = Sha256::digest([&h0123[..], &leaves[4][..]].concat());
+
+    let tree = match h.hash_data_tree(&mut data.as_slice(), 1000) {
+        Ok(t) => t,
+        Err(e) => panic!("{}", e.message())
+    };
+
+    assert_eq!(tree.leaf_count(), 5);
+    assert_eq!(tree.root(), &root[..]);
+    assert_eq!(Ok(to_hex_string(tree.root())), tree_h.hash_data(&mut data.as_slice()).map_err(|e| e.message()));
+    assert_eq!(tree_h.get_algo(), "SHA-256-TREE-1000");
+
+    for i in 0..tree.leaf_count() {
+        let proof = tree.proof(i).unwrap();
+        assert!(verify_proof(tree.leaf(i).unwrap(), &proof, tree.root(), &mut Sha256::new()));
+        assert!(!verify_proof(tree.leaf((i + 1) % 5).unwrap(), &proof, tree.root(), &mut Sha256::new()));
+    }
+
+    assert!(tree.proof(5).is_none());
+
+    // Data smaller than a leaf has the same root as the plain hash
+    let mut small = "abc".as_bytes();
+    assert_eq!(tree_h.hash_data(&mut small).ok().unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+}
+
+#[cfg(test)]
+fn make_test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("rs256sum_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    return dir;
+}
+
+#[test]
+fn dir_hash_test() {
+    let dir = make_test_dir("dirhash");
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub").join("b.txt"), "abc").unwrap();
+    std::fs::write(dir.join("a.txt"), "").unwrap();
+    std::fs::write(dir.join("sub.txt"), "abc").unwrap();
+
+    let mut h = Hasher::new("SHA-256", Box::new(Sha256::new()));
+    let records = DirHasher::new(&mut h, false).serialize(&dir).ok().unwrap();
+    let expected = "f - e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855 a.txt\0\
+                    d - - sub\0\
+                    f - ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad sub/b.txt\0\
+                    f - ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad sub.txt\0";
+    assert_eq!(String::from_utf8(records).unwrap(), expected);
+
+    let dir_hash = DirHasher::new(&mut h, false).hash_dir(&dir).ok().unwrap();
+    assert_eq!(Ok(dir_hash), h.hash_data(&mut expected.as_bytes()).map_err(|e| e.message()));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cdc_test() {
+    let mut h = Hasher::new("SHA-256", Box::new(Sha256::new()));
+    let chunker = Chunker::new(1024).unwrap();
+    let mut state: u32 = 1;
+    let random: Vec<u8> = (0..50000).map(|_| { state = state.wrapping_mul(1103515245).wrapping_add(12345); (state >> 16) as u8 }).collect();
+
+    // A shifted copy of the data has to produce mostly the same chunks
+    let mut data = random.clone();
+    data.extend_from_slice(b"some bytes in between");
+    data.extend_from_slice(&random);
+
+    let chunks = match chunker.chunk_data(&mut data.as_slice(), &mut h) {
+        Ok(c) => c,
+        Err(e) => panic!("{}", e.message())
+    };
+
+    let mut stats = DedupStats::new();
+    let mut offset: u64 = 0;
+
+    for (i, c) in chunks.iter().enumerate() {
+        assert_eq!(c.offset, offset);
+        assert!(c.length <= 8192);
+        assert!((c.length >= 256) || (i == chunks.len() - 1));
+        offset += c.length;
+        stats.add(c);
+    }
+
+    assert_eq!(offset, data.len() as u64);
+    assert_eq!(stats.total_bytes, data.len() as u64);
+    assert!(stats.duplicate_bytes() > 45000);
+    assert!(Chunker::new(1000).is_none());
+}
+
+#[test]
+fn region_test() {
+    let data: Vec<u8> = (0..10000u32).map(|x| (x % 249) as u8).collect();
+    let region = Region::new(100, Some(5000), vec![(3000, 500), (50, 100), (4000, 2000)]);
+    let mut read_back: Vec<u8> = Vec::new();
+
+    // The reader starts at the region offset
+    RegionReader::new(&data[100..], &region).read_to_end(&mut read_back).unwrap();
+
+    let mut expected: Vec<u8> = data[150..3000].to_vec();
+    expected.extend_from_slice(&data[3500..4000]);
+    assert_eq!(read_back, expected);
+
+    // Regions which extend past the end of the data are errors
+    assert!(RegionReader::new(&data[9000..], &Region::new(9000, Some(2000), vec![])).read_to_end(&mut Vec::new()).is_err());
+    assert!(RegionReader::new(&data[9000..], &Region::new(9000, None, vec![])).read_to_end(&mut Vec::new()).is_ok());
+
+    let dir = make_test_dir("region");
+    let file_name = dir.join("short");
+    std::fs::write(&file_name, b"abc").unwrap();
+    let mut h = Hasher::new(ALGO_SHA256, Box::new(Sha256::new()));
+    assert!(matches!(h.hash_file_region(&file_name, &Region::new(10, Some(20), vec![])), Err(HashError::ReadError(Some(_), _, _))));
+    assert!(matches!(h.hash_file_region(&file_name, &Region::new(10, None, vec![])), Err(HashError::ReadError(Some(_), _, _))));
+    assert!(matches!(h.hash_file_region(&file_name, &Region::new(1, Some(5), vec![])), Err(HashError::ReadError(Some(_), _, _))));
+    assert!(h.hash_file_region(&file_name, &Region::new(3, None, vec![])).is_ok());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let line = region.format();
+    assert_eq!(line, "REGION 100 5000 50+100 3000+500 4000+2000");
+    assert_eq!(Region::parse(&line).ok(), Some(region));
+    assert_eq!(Region::parse("REGION 0 -").ok(), Some(Region::default()));
+    assert!(Region::parse("REGION 0 - 17").is_err());
+
+    let ref_text = String::from("111111  dateia\nREGION 512 - 1024+64\n222222  dateib\n");
+    let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
+    let ref_data = RefFile::new(ref_text.as_bytes(), &f);
+    let res: Vec<RefEntry> = ref_data.into_iter().map(|x| x.ok().unwrap()).collect();
+
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[0].region, Region::new(512, None, vec![(1024, 64)]));
+    assert!(res[1].region.is_whole());
+
+    // The region line of a malformed entry line is skipped with it
+    let ref_text = String::from("111111 dateia\nREGION 512 -\n222222  dateib\nREGION 0 10\n");
+    let res: Vec<Result<RefEntry, RefFileError>> = RefFile::new(ref_text.as_bytes(), &f).into_iter().collect();
+
+    assert_eq!(res.len(), 2);
+    assert!(matches!(res[0], Err(RefFileError::Malformed(1, _))));
+    assert_eq!(res[1].as_ref().ok().unwrap().region, Region::new(0, Some(10), vec![]));
+
+    let res: Vec<Result<ChunkedEntry, RefFileError>> = ChunkedRefFile::new(ref_text.as_bytes(), &f).collect();
+
+    assert_eq!(res.len(), 2);
+    assert!(matches!(res[0], Err(RefFileError::Malformed(1, _))));
+    assert_eq!(res[1].as_ref().ok().unwrap().region, Region::new(0, Some(10), vec![]));
+}
+
+#[cfg(unix)]
+#[test]
+fn escape_test() {
+    let raw_name = path_from_bytes(b"new\nline\\\xff.txt".to_vec());
+    let simple = SimpleFormatter::new();
+    let bsd = BsdFormatter::from_str(ALGO_SHA256);
+
+    let line = simple.format(&String::from("abcdef"), &raw_name);
+    assert_eq!(line, "\\abcdef  new\\nline\\\\\\xff.txt");
+    assert_eq!(simple.parse(&line).ok().unwrap().0, raw_name);
+
+    let line = bsd.format(&String::from("abcdef"), &raw_name);
+    assert_eq!(line, "\\SHA256 (new\\nline\\\\\\xff.txt) = abcdef");
+    assert_eq!(bsd.parse(&line).ok().unwrap().0, raw_name);
+
+    assert_eq!(simple.format(&String::from("abcdef"), Path::new("data.txt")), "abcdef  data.txt");
+    assert!(simple.parse(&String::from("\\abcdef  data\\q.txt")).is_err());
+
+    // Whitespace at the start or the end of a name survives a round trip
+    for name in [" lead", "trail ", "\tboth\u{a0}", " ", "in side"] {
+        let line = simple.format(&String::from("abcdef"), Path::new(name));
+        assert_eq!(simple.parse(&line).ok().unwrap().0, Path::new(name));
+        let line = bsd.format(&String::from("abcdef"), Path::new(name));
+        assert_eq!(bsd.parse(&line).ok().unwrap().0, Path::new(name));
+    }
+
+    assert_eq!(simple.format(&String::from("abcdef"), Path::new(" a b ")), "\\abcdef  \\x20a b\\x20");
+    assert_eq!(simple.parse(&String::from("abcdef  data.txt \t")).ok().unwrap().0, Path::new("data.txt"));
+
+    // Raw bytes in reference data are escaped when reading
+    let ref_data: Vec<u8> = b"111111  \xfe\xff\n".to_vec();
+    let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
+    let res: Vec<RefEntry> = RefFile::new(ref_data.as_slice(), &f).into_iter().map(|x| x.ok().unwrap()).collect();
+    assert_eq!(res[0].file_name, path_from_bytes(vec![0xfe, 0xff]));
+}
+
+#[test]
+fn encoding_test() {
+    let hash = Sha256::digest(b"").to_vec();
+    let encoded: Vec<String> = [Encoding::Hex, Encoding::Base64, Encoding::Base32, Encoding::Base58].iter().map(|e| e.encode(&hash)).collect();
+
+    assert_eq!(encoded[0], "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(encoded[1], "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=");
+    assert_eq!(encoded[2], "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73");
+    assert_eq!(encoded[3], "GKot5hBsd81kMupNCXHaqbhv3huEbxAFMLnpcX2hniwn");
+
+    for e in &encoded {
+        assert_eq!(decode_any(e, hash.len()), Some(hash.clone()));
+    }
+
+    assert_eq!(Encoding::Base58.decode("1112"), Some(vec![0, 0, 0, 1]));
+    assert_eq!(Encoding::Base58.encode(&[0, 0, 0, 1]), "1112");
+    assert_eq!(decode_any("0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c7", hash.len()), None);
+
+    let f = EncodedFormatter::new(Rc::new(SimpleFormatter::new()), Encoding::Base64);
+    let line = f.format(&encoded[0], Path::new("empty"));
+    assert_eq!(line, "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=  empty");
+    assert_eq!(f.parse(&line).ok().unwrap().1, encoded[1]);
+}
+
+#[test]
+fn encoded_ref_data_test() {
+    let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
+    let bsd = BsdFormatter::from_str(ALGO_SHA256);
+    let empty_hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    let ref_data = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=  base64\n\
+        0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73  base32\n\
+        GKot5hBsd81kMupNCXHaqbhv3huEbxAFMLnpcX2hniwn  base58\n\
+        E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855  upper\n";
+
+    let entries: Vec<RefEntry> = RefFile::new(ref_data.as_bytes(), &f).into_iter().map(|x| x.ok().unwrap()).collect();
+    assert_eq!(entries.len(), 4);
+
+    for e in &entries {
+        assert!(matches!(compare_hashes(ALGO_SHA256, &e.hash, empty_hash), HashError::Ok));
+    }
+
+    let (name, hash) = bsd.parse("SHA256 (base58) = GKot5hBsd81kMupNCXHaqbhv3huEbxAFMLnpcX2hniwn").ok().unwrap();
+    assert_eq!(name, Path::new("base58"));
+    assert!(matches!(compare_hashes(ALGO_SHA256, &hash, empty_hash), HashError::Ok));
+
+    // Characters outside of all encodings are rejected by the parser
+    assert!(f.parse("47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU%  base64").is_err());
+    assert!(bsd.parse("SHA256 (x) = e3b0c442-98fc").is_err());
+}
+
+#[test]
+fn multi_digest_test() {
+    let digests: Vec<Box<dyn digest::DynDigest>> = vec![Box::new(Sha256::new()), Box::new(sha2::Sha512::new()), Box::new(blake3::Hasher::new())];
+    let mut h = Hasher::new("SHA256+SHA512+BLAKE3", Box::new(MultiDigest::new(digests)));
+    let hash = h.hash_data(&mut "abc".as_bytes()).ok().unwrap();
+
+    assert_eq!(hash.len(), 2 * (32 + 64 + 32));
+    assert_eq!(&hash[..64], "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(&hash[64..192], to_hex_string(&sha2::Sha512::digest(b"abc")));
+    assert_eq!(&hash[192..], blake3::hash(b"abc").to_hex().as_str());
+
+    // The digests are reset for the next file
+    assert_eq!(h.hash_data(&mut "abc".as_bytes()).ok().unwrap(), hash);
+}
+
+#[test]
+fn compressed_ref_data_test() {
+    use std::io::Write;
+
+    let data = "111111  dateia\n222222  dateib\n";
+
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(data.as_bytes()).unwrap();
+    let gz_data = gz.finish().unwrap();
+    let zstd_data = zstd::encode_all(data.as_bytes(), 0).unwrap();
+    let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+    xz.write_all(data.as_bytes()).unwrap();
+    let xz_data = xz.finish().unwrap();
+
+    assert_eq!(detect(&gz_data), Compression::Gzip);
+    assert_eq!(detect(&zstd_data), Compression::Zstd);
+    assert_eq!(detect(&xz_data), Compression::Xz);
+    assert_eq!(detect(data.as_bytes()), Compression::Uncompressed);
+
+    let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
+
+    for d in [gz_data.as_slice(), zstd_data.as_slice(), xz_data.as_slice(), data.as_bytes()] {
+        let ref_data = RefFile::new(decompress(d).unwrap(), &f);
+        let res: Vec<RefEntry> = ref_data.into_iter().map(|x| x.ok().unwrap()).collect();
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[1].hash, "222222");
+        assert_eq!(res[1].file_name, Path::new("dateib"));
+    }
+}
+
+#[test]
+fn archive_test() {
+    let dir = make_test_dir("archive");
+    let tar_name = dir.join("data!1.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&tar_name).unwrap());
+
+    for (path, data) in [("a.txt", "abc"), ("sub/b.txt", "")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, data.as_bytes()).unwrap();
+    }
+
+    builder.finish().unwrap();
+    drop(builder);
+
+    let mut h = ArchiveHasher::new(Box::new(Hasher::new(ALGO_SHA256, Box::new(Sha256::new()))));
+    let names = h.expand_name(&tar_name).ok().unwrap();
+
+    assert_eq!(names, vec![member_name(&tar_name, b"a.txt"), member_name(&tar_name, b"sub/b.txt")]);
+    assert_eq!(split_member_name(&names[1]), Some((tar_name.clone(), b"sub/b.txt".to_vec())));
+    // Only archives are taken as the archive part of a name
+    std::fs::write(dir.join("data"), b"abc").unwrap();
+    assert_eq!(split_member_name(&names[1]), Some((tar_name.clone(), b"sub/b.txt".to_vec())));
+    assert_eq!(split_member_name(&dir.join("data!1.txt")), None);
+    assert_eq!(h.hash_file(&names[0]).ok().unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert!(matches!(h.verify_file(&names[1], "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"), HashError::Ok));
+    assert!(matches!(h.hash_file(&member_name(&tar_name, b"c.txt")), Err(HashError::FileOpenError(_, _))));
+
+    // A path stored twice is listed once with the data which is extracted last
+    let dup_name = dir.join("dup.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&dup_name).unwrap());
+
+    for (path, data) in [("a.txt", "old"), ("b.txt", ""), ("a.txt", "abc")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, data.as_bytes()).unwrap();
+    }
+
+    builder.finish().unwrap();
+    drop(builder);
+
+    let names = h.expand_name(&dup_name).ok().unwrap();
+    assert_eq!(names, vec![member_name(&dup_name, b"a.txt"), member_name(&dup_name, b"b.txt")]);
+    assert_eq!(h.hash_file(&names[0]).ok().unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn decompress_hasher_test() {
+    use std::io::Write;
+
+    let dir = make_test_dir("decompress");
+    let gz_name = dir.join("data.gz");
+    let plain_name = dir.join("data");
+    let abc_hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string();
+
+    let mut gz = flate2::write::GzEncoder::new(std::fs::File::create(&gz_name).unwrap(), flate2::Compression::default());
+    gz.write_all(b"abc").unwrap();
+    gz.finish().unwrap();
+    std::fs::write(&plain_name, b"abc").unwrap();
+
+    let mut h = DecompressHasher::new(Box::new(Hasher::new(ALGO_SHA256, Box::new(Sha256::new()))));
+
+    assert_eq!(h.hash_file(&gz_name).ok().unwrap(), abc_hash);
+    assert_eq!(h.hash_file(&plain_name).ok().unwrap(), abc_hash);
+    assert!(matches!(h.verify_file(&gz_name, &abc_hash), HashError::Ok));
+    assert!(matches!(h.verify_file(&gz_name, &abc_hash.replace('b', "c")), HashError::HashVerifyFail(_)));
+    // SHA256("bc")
+    assert_eq!(h.hash_file_region(&gz_name, &Region::new(1, None, vec![])).ok().unwrap(), "1e0bbd6c686ba050b8eb03ffeedc64fdc9d80947fce821abbe5d6dc8d252c5ac");
+    assert!(matches!(h.hash_file(&dir.join("missing.gz")), Err(HashError::FileOpenError(_, _))));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn watch_test() {
+    let dir = make_test_dir("watch");
+    let file_name = dir.join("data");
+    std::fs::write(&file_name, b"abc").unwrap();
+
+    let mut h = Hasher::new(ALGO_SHA256, Box::new(Sha256::new()));
+    let mut watcher = crate::watch::Watcher::new(&mut h).unwrap();
+    let entry = RefEntry {
+        line_no: 1,
+        file_name: file_name.clone(),
+        hash: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+        region: Region::default()
+    };
+
+    assert!(matches!(watcher.verify(&entry), HashError::Ok));
+    watcher.add(entry).ok().unwrap();
+    assert!(watcher.add(RefEntry { line_no: 2, file_name: Path::new("-").to_path_buf(), hash: String::new(), region: Region::default() }).is_err());
+    let duplicate = RefEntry { line_no: 3, file_name: file_name.clone(), hash: String::new(), region: Region::default() };
+    assert!(matches!(watcher.add(duplicate), Err(HashError::DuplicateEntry(_))));
+
+    // Data written through a descriptor which stays open is noticed as well
+    let mut open_file = std::fs::OpenOptions::new().append(true).open(&file_name).unwrap();
+    open_file.write_all(b"d").unwrap();
+    let res = watcher.wait().unwrap();
+
+    assert_eq!(res.len(), 1);
+    assert!(matches!(res[0].1, HashError::HashVerifyFail(_)));
+    drop(open_file);
+
+    std::fs::write(&file_name, b"abd").unwrap();
+    let res = watcher.wait().unwrap();
+
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].0, file_name);
+    assert!(matches!(res[0].1, HashError::HashVerifyFail(_)));
+
+    std::fs::remove_file(&file_name).unwrap();
+    let res = watcher.wait().unwrap();
+
+    assert!(matches!(res[0].1, HashError::FileOpenError(_, _)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scrub_state_test() {
+    use crate::scrub::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    let state = ScrubState::load("200 b\n100 a\n\\300 c\\nd\n".as_bytes()).unwrap();
+
+    assert_eq!(state.last_checked(Path::new("a")), 100);
+    assert_eq!(state.last_checked(Path::new("c\nd")), 300);
+    assert_eq!(state.last_checked(Path::new("new")), 0);
+    assert!(ScrubState::load("abc b\n".as_bytes()).is_err());
+
+    let make_entry = |name: &str| RefEntry { line_no: 0, file_name: PathBuf::from(name), hash: String::new(), region: Region::default() };
+    let mut entries = vec![make_entry("b"), make_entry("c\nd"), make_entry("new"), make_entry("a")];
+    state.order(&mut entries);
+    let order: Vec<&Path> = entries.iter().map(|e| e.file_name.as_path()).collect();
+
+    assert_eq!(order, vec![Path::new("new"), Path::new("a"), Path::new("b"), Path::new("c\nd")]);
+
+    let mut state = state;
+    state.mark_checked(Path::new("new"), 400);
+    let mut saved: Vec<u8> = Vec::new();
+    state.save(&mut saved, &[PathBuf::from("new"), PathBuf::from("c\nd"), PathBuf::from("b")]).unwrap();
+
+    assert_eq!(String::from_utf8(saved).unwrap(), "400 new\n\\300 c\\nd\n200 b\n");
+
+    let dir = make_test_dir("scrub_state");
+    let state_file = dir.join("scrub.state");
+    std::fs::write(&state_file, "old").unwrap();
+    state.save_file(&state_file, &[PathBuf::from("a")]).unwrap();
+
+    assert_eq!(std::fs::read_to_string(&state_file).unwrap(), "100 a\n");
+    assert!(!dir.join("scrub.state.tmp").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let budget = Budget { time: parse_duration("2m"), bytes: Some(1000) };
+
+    assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+    assert_eq!(parse_duration("0"), None);
+    assert!(!budget.is_used(Duration::from_secs(119), 999));
+    assert!(budget.is_used(Duration::from_secs(120), 0));
+    assert!(budget.is_used(Duration::from_secs(0), 1000));
+}
+
+#[test]
+fn xattr_test() {
+    use crate::xattrs::*;
+
+    let dir = make_test_dir("xattr");
+    let file_name = dir.join("data");
+    std::fs::write(&file_name, b"abc").unwrap();
+
+    let mut h = XattrHasher::new(Box::new(Hasher::new(ALGO_SHA256, Box::new(Sha256::new()))));
+    let abc_hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    assert!(matches!(read_stored_hash(&file_name), Err(HashError::StoredHashMissing(_))));
+    assert_eq!(h.hash_file(&file_name).ok().unwrap(), abc_hash);
+
+    let stored = read_stored_hash(&file_name).ok().unwrap();
+    assert_eq!(stored.algo, ALGO_SHA256);
+    assert_eq!(stored.hash, abc_hash);
+    assert!(StoredHash::parse(&stored.to_value()).is_some());
+    assert!(StoredHash::parse("SHA256 abc").is_none());
+
+    // Verification does not replace the stored hash
+    let mut other = XattrHasher::new(make_hash_for_algo(ALGO_SHA512).unwrap());
+    assert!(matches!(other.verify_file(&file_name, &"0".repeat(128)), HashError::HashVerifyFail(_)));
+    assert_eq!(read_stored_hash(&file_name).ok().unwrap().algo, ALGO_SHA256);
+
+    // The hasher is chosen by the stored algorithm
+    assert!(matches!(verify_stored_hash(&mut make_hash_for_algo, &file_name), (HashError::Ok, false)));
+
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    std::fs::write(&file_name, b"abd").unwrap();
+    assert!(matches!(verify_stored_hash(&mut make_hash_for_algo, &file_name), (HashError::HashVerifyFail(_), true)));
+
+    let mut tree = XattrHasher::new(make_hash_for_algo("SHA512-TREE-1024").unwrap());
+    tree.hash_file(&file_name).ok().unwrap();
+    assert_eq!(read_stored_hash(&file_name).ok().unwrap().algo, "SHA512-TREE-1024");
+    assert!(matches!(verify_stored_hash(&mut make_hash_for_algo, &file_name), (HashError::Ok, false)));
+
+    assert_eq!(make_hash_for_algo("GIT-SHA1").unwrap().get_algo(), "GIT-SHA1");
+    assert!(make_hash_for_algo("GIT-BLAKE3").is_none());
+    assert!(make_hash_for_algo("SHA256-TREE-0").is_none());
+
+    xattr::set(&file_name, XATTR_NAME, b"MD5 abc 1.000000000").unwrap();
+    let (res, _) = verify_stored_hash(&mut make_hash_for_algo, &file_name);
+    assert!(matches!(res, HashError::StoredHashInvalid(_, _)));
+    assert_eq!(error_status(&res), PROG_RETURN_MALFORMED);
+    assert!(matches!(h.hash_file_region(&file_name, &Region::new(1, None, vec![])), Err(HashError::UnsupportedFileType(_))));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sri_test() {
+    use crate::sri::*;
+
+    let abc_sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let abc_sha256_b64 = "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=";
+    let f = SriFormatter::new(vec![("sha256".to_string(), 64), ("sha512".to_string(), 128)]);
+    let both = format!("{}{}", abc_sha256, "00".repeat(64));
+    let line = f.format(&both, Path::new("a b.js"));
+
+    assert_eq!(line, format!("sha256-{} sha512-{}==  a b.js", abc_sha256_b64, "A".repeat(86)));
+    assert_eq!(f.parse(&line).ok().unwrap(), (Path::new("a b.js").to_path_buf(), abc_sha256_b64.to_string()));
+    assert!(f.parse("sha384-abc  a.js").is_err());
+    assert!(f.parse("sha256-abc").is_err());
+
+    // Whitespace around names survives a round trip
+    for name in [" a.js", "a.js "] {
+        let line = f.format(&both, Path::new(name));
+        assert_eq!(f.parse(&line).ok().unwrap().0, Path::new(name));
+    }
+
+    let tokens = parse_integrity("sha256-abc md5-xyz sha384-def?opt sha384-ghi");
+    assert_eq!(tokens.len(), 3);
+    assert_eq!(tokens[1], SriToken { algo: "sha384".to_string(), hash: "def".to_string() });
+    let strongest: Vec<String> = strongest_tokens(&tokens).into_iter().map(|t| t.hash).collect();
+    assert_eq!(strongest, vec!["def", "ghi"]);
+
+    let html = "<SCRIPT integrity=\"sha256-abc\" SRC='a.js'></SCRIPT>\n<link href=/b.css\nintegrity=\"sha384-def\"><script src=\"c.js\"></script>\n\
+        <script src=\"d.js\" data-src=\"lazy.js\" integrity=\"sha256-ghi\" data-integrity=\"sha256-xyz\"></script>";
+    let resources = find_resources(html);
+    assert_eq!(resources, vec![
+        SriResource { url: "a.js".to_string(), integrity: "sha256-abc".to_string() },
+        SriResource { url: "/b.css".to_string(), integrity: "sha384-def".to_string() },
+        SriResource { url: "d.js".to_string(), integrity: "sha256-ghi".to_string() }
+    ]);
+
+    let site = Path::new("root/site");
+    assert_eq!(local_path("js/a.js?v=2", site, Path::new("root")), Some(Path::new("root/site/js/a.js").to_path_buf()));
+    assert_eq!(local_path("/b.css#x", site, Path::new("root")), Some(Path::new("root/b.css").to_path_buf()));
+    assert_eq!(local_path("https://example.com/a.js", site, Path::new("root")), None);
+    assert_eq!(local_path("//example.com/a.js", site, Path::new("root")), None);
+
+    // Paths are percent-decoded and have to stay in the root directory
+    assert_eq!(local_path("my%20app.js", site, Path::new("root")), Some(Path::new("root/site/my app.js").to_path_buf()));
+    assert_eq!(local_path("./js/../a%zz.js", site, Path::new("./root")), Some(Path::new("root/site/a%zz.js").to_path_buf()));
+    assert_eq!(local_path("../a.js", site, Path::new("root")), Some(Path::new("root/a.js").to_path_buf()));
+    assert_eq!(local_path("../../etc/passwd", site, Path::new("root")), None);
+    assert_eq!(local_path("/%2e%2e/secret.js", site, Path::new("root")), None);
+    assert_eq!(local_path("a.js", Path::new("other"), Path::new("root")), None);
+}
+
+#[test]
+fn oci_layout_test() {
+    use crate::oci::*;
+
+    let dir = make_test_dir("oci");
+    let blob_dir = dir.join("blobs").join("sha256");
+    std::fs::create_dir_all(&blob_dir).unwrap();
+
+    let write_blob = |data: &[u8]| -> (String, usize) {
+        let mut h = Hasher::new(ALGO_SHA256, Box::new(Sha256::new()));
+        let hex = h.hash_data(&mut &data[..]).ok().unwrap();
+        std::fs::write(blob_dir.join(&hex), data).unwrap();
+        return (format!("sha256:{}", hex), data.len());
+    };
+
+    let (layer, layer_size) = write_blob(b"layer data");
+    let (config, config_size) = write_blob(b"{}");
+    let (unused, _) = write_blob(b"unused");
+    let manifest_data = format!(
+        r#"{{"config": {{"mediaType": "application/vnd.oci.image.config.v1+json", "digest": "{}", "size": {}}},
+        "layers": [{{"mediaType": "application/vnd.oci.image.layer.v1.tar", "digest": "{}", "size": {}}},
+        {{"mediaType": "application/vnd.oci.image.layer.v1.tar", "digest": "sha256:{}", "size": 1}}]}}"#,
+        config, config_size, layer, layer_size + 1, "0".repeat(64));
+    let (manifest, manifest_size) = write_blob(manifest_data.as_bytes());
+
+    std::fs::write(dir.join("oci-layout"), r#"{"imageLayoutVersion": "1.0.0"}"#).unwrap();
+    std::fs::write(dir.join("index.json"), format!(r#"{{"manifests": [{{"mediaType": "application/vnd.oci.image.manifest.v1+json", "digest": "{}", "size": {}}}]}}"#, manifest, manifest_size)).unwrap();
+    std::fs::write(blob_dir.join(&config[7..]), b"[]").unwrap();
+
+    let mut hasher_for = |algo: &str| -> Option<Box<dyn FileHash>> {
+        if algo == "sha256" { Some(Box::new(Hasher::new(ALGO_SHA256, Box::new(Sha256::new())))) } else { None }
+    };
+    let reports = LayoutChecker::new(&dir, &mut hasher_for).check();
+    let count = |f: &dyn Fn(&OciReport) -> bool| reports.iter().filter(|r| f(r)).count();
+    let count_malformed = |reports: &Vec<OciReport>| reports.iter().filter(|r| matches!(r, OciReport::Malformed(_, _))).count();
+
+    assert_eq!(count(&|r| matches!(r, OciReport::BlobOk(_))), 3);
+    assert_eq!(count(&|r| matches!(r, OciReport::BlobFailed(_, HashError::HashVerifyFail(_)))), 1);
+    assert_eq!(count(&|r| matches!(r, OciReport::Missing(d, m) if d.ends_with("0000") && *m == manifest)), 1);
+    assert_eq!(count(&|r| matches!(r, OciReport::WrongSize(d, _, _) if *d == layer)), 1);
+    assert_eq!(count(&|r| matches!(r, OciReport::Unreferenced(p) if p.ends_with(&unused[7..]))), 1);
+    assert_eq!(reports.len(), 7);
+
+    // Blob names in other encodings than lowercase hex and stray files are reported
+    std::fs::write(blob_dir.join(unused[7..].to_uppercase()), b"unused").unwrap();
+    std::fs::write(dir.join("blobs").join("stray"), b"").unwrap();
+    let reports = LayoutChecker::new(&dir, &mut hasher_for).check();
+    assert_eq!(count_malformed(&reports), 2);
+    assert_eq!(reports.iter().filter(|r| matches!(r, OciReport::BlobOk(_))).count(), 3);
+
+    std::fs::remove_file(dir.join("oci-layout")).unwrap();
+    let reports = LayoutChecker::new(&dir, &mut hasher_for).check();
+    assert!(matches!(reports[..], [OciReport::Malformed(_, _)]));
+
+    let parser = SimpleFormatter::new();
+    assert_eq!(parser.parse(&format!("{}  data", layer)).ok().unwrap().1, layer);
+    let upper_prefix = format!("SHA256:{}", &layer[7..]);
+    assert_eq!(parser.parse(&format!("{}  data", upper_prefix)).ok().unwrap().1, upper_prefix);
+
+    // The algorithm of a hash in digest notation has to match
+    let mut h = Hasher::new(ALGO_SHA256, Box::new(Sha256::new()));
+    let abc_hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    assert!(matches!(h.verify_data(&mut "abc".as_bytes(), &format!("sha256:{}", abc_hash)), HashError::Ok));
+    assert!(matches!(h.verify_data(&mut "abc".as_bytes(), &format!("SHA256:{}", abc_hash)), HashError::Ok));
+    assert!(matches!(h.verify_data(&mut "abc".as_bytes(), &format!("sha512:{}", abc_hash)), HashError::WrongHashLength(_, _)));
+    assert!(matches!(compare_hashes("SHA256-TREE-1024", &format!("sha256:{}", abc_hash), abc_hash), HashError::WrongHashLength(_, _)));
+    let digest_formatter = DigestFormatter::new(Rc::new(SimpleFormatter::new()), ALGO_SHA256);
+    assert_eq!(digest_formatter.format("abcd", Path::new("data")), "sha256:abcd  data");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn git_object_test() {
+    let mut h = Hasher::new_git("SHA1", Box::new(sha1::Sha1::new()));
+
+    assert_eq!(h.get_algo(), "GIT-SHA1");
+    assert_eq!(h.hash_data(&mut "hello\n".as_bytes()).ok().unwrap(), "ce013625030ba8dba906f756967f9e9ca394464a");
+    assert_eq!(h.hash_data(&mut "".as_bytes()).ok().unwrap(), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+    assert!(h.hash_data_chunked(&mut "".as_bytes(), 4).is_err());
+
+    let dir = make_test_dir("git");
+    std::fs::create_dir_all(dir.join("d")).unwrap();
+    std::fs::create_dir_all(dir.join("e")).unwrap();
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    std::fs::write(dir.join("a"), b"x\n").unwrap();
+    std::fs::write(dir.join("d").join("b"), b"y\n").unwrap();
+
+    assert_eq!(h.hash_file(&dir.join("a")).ok().unwrap(), h.hash_data(&mut "x\n".as_bytes()).ok().unwrap());
+    assert_eq!(GitTreeHasher::new(&mut h).hash_dir(&dir).ok().unwrap(), "de50477a049e830eb9235df2eb329f9d889de308");
+    assert_eq!(GitTreeHasher::new(&mut h).hash_dir(&dir.join("e")).ok().unwrap(), "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn pgp_injection_test() {
+    use std::process::Command;
+
+    let dir = make_test_dir("pgp");
+    let gpg = |args: &[&str]| Command::new("gpg").env("GNUPGHOME", &dir).args(["--batch", "--quiet"]).args(args).output();
+
+    // gpg is needed to create the signature
+    match gpg(&["--passphrase", "", "--quick-gen-key", "Test <test@example.com>", "ed25519", "sign", "never"]) {
+        Ok(out) if out.status.success() => {},
+        _ => {
+            eprintln!("gpg is not available, skipping pgp_injection_test");
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+    }
+
+    let keyring = dir.join("keyring.gpg");
+    std::fs::write(&keyring, gpg(&["--export"]).unwrap().stdout).unwrap();
+    std::fs::write(dir.join("SUMS"), b"111111  dateia\n- dash\n").unwrap();
+    assert!(gpg(&["--clearsign", "-o", dir.join("SUMS.asc").to_str().unwrap(), dir.join("SUMS").to_str().unwrap()]).unwrap().status.success());
+
+    let mut signed = b"999999  injected_before\n".to_vec();
+    signed.extend(std::fs::read(dir.join("SUMS.asc")).unwrap());
+    signed.extend(b"888888  injected_after\n");
+
+    let verifier = make_verifier(SIG_TYPE_PGP, &keyring).ok().unwrap();
+    let verified = verifier.verify(&signed, None).ok().unwrap();
+    assert_eq!(verified, b"111111  dateia\n- dash\n");
+
+    let mut tampered = std::fs::read(dir.join("SUMS.asc")).unwrap();
+    let pos = tampered.windows(6).position(|w| w == b"111111").unwrap();
+    tampered[pos] = b'2';
+    assert!(verifier.verify(&tampered, None).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Hashes the files listed in input in dir and returns the number of hashed files, the exit
+// status and the reference data
+#[cfg(test)]
+fn hash_list(dir: &Path, input: &[u8], separator: u8, names_from_stdin: bool, fail_fast: bool) -> (u32, i32, String) {
+    let out_name = dir.join("SUMS");
+    let mut outputs = vec![ManifestOutput {
+        formatter: make_formatter(ALGO_SHA256, false),
+        hash_part: 0..64,
+        writer: Box::new(std::fs::File::create(&out_name).unwrap())
+    }];
+    let mut h = Hasher::new(ALGO_SHA256, Box::new(Sha256::new()));
+
+    let (count, status) = hash_listed_files(input, separator, names_from_stdin, &mut h, &mut outputs, None, &Region::default(), fail_fast);
+    drop(outputs);
+
+    return (count, status, std::fs::read_to_string(&out_name).unwrap());
+}
+
+#[test]
+fn stdin_name_test() {
+    let dir = make_test_dir("stdin_name");
+    std::fs::write(dir.join("a"), "abc").unwrap();
+    std::fs::write(dir.join("b"), "").unwrap();
+    let input = format!("{0}/a\n-\n{0}/b\n", dir.display());
+
+    // - is rejected instead of waiting for data on stdin
+    let (count, status, sums) = hash_list(&dir, input.as_bytes(), b'\n', true, false);
+    assert_eq!((count, status), (2, PROG_RETURN_USAGE));
+    assert_eq!(sums.lines().count(), 2);
+
+    let (count, status, _) = hash_list(&dir, input.as_bytes(), b'\n', true, true);
+    assert_eq!((count, status), (1, PROG_RETURN_USAGE));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stdin_ref_data_test() {
+    let dir = make_test_dir("stdin_ref_data");
+    std::fs::write(dir.join("a"), "abc").unwrap();
+    let f: Rc<dyn HashLineFormatter> = Rc::new(SimpleFormatter::new());
+    let mut h = Hasher::new(ALGO_SHA256, Box::new(Sha256::new()));
+
+    // The rest of the reference data must not be hashed as the data of -
+    let ref_data = format!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  -\n\
+        ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  {}\n", dir.join("a").display());
+
+    for use_chunks in [false, true] {
+        assert_eq!(verify_input(ref_data.as_bytes(), &f, &mut h, use_chunks, true), (PROG_RETURN_USAGE, 0));
+    }
+
+    let ref_data = format!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  {}\n", dir.join("a").display());
+    assert_eq!(verify_input(ref_data.as_bytes(), &f, &mut h, false, true), (PROG_RETURN_OK, 0));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn listed_files_test() {
+    let dir = make_test_dir("listed_files");
+    let abc_hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    std::fs::write(dir.join("a"), "abc").unwrap();
+    std::fs::write(dir.join("b c"), "abc").unwrap();
+    std::fs::write(dir.join("d\r"), "abc").unwrap();
+    let name = |n: &str| format!("{}/{}", dir.display(), n);
+
+    // Newline separated names with a carriage return at the end of a line and empty names
+    let input = format!("{}\r\n\n{}\n\r\n{}", name("a"), name("b c"), name("a"));
+    let (count, status, sums) = hash_list(&dir, input.as_bytes(), b'\n', false, false);
+    assert_eq!((count, status), (3, PROG_RETURN_OK));
+    assert_eq!(sums, format!("{0}  {1}\n{0}  {2}\n{0}  {1}\n", abc_hash, name("a"), name("b c")));
+
+    // A carriage return at the end of a NUL separated name is part of the name
+    let input = format!("{}\0\0{}\0", name("d\r"), name("b c"));
+    let (count, status, sums) = hash_list(&dir, input.as_bytes(), b'\0', false, false);
+    assert_eq!((count, status), (2, PROG_RETURN_OK));
+    assert_eq!(sums, format!("\\{0}  {1}\\r\n{0}  {2}\n", abc_hash, name("d"), name("b c")));
+
+    // A missing file does not stop hashing the remaining files unless fail_fast is set
+    let input = format!("{}\n{}\n", name("x"), name("a"));
+    assert_eq!(hash_list(&dir, input.as_bytes(), b'\n', false, false).0, 1);
+    assert_eq!(hash_list(&dir, input.as_bytes(), b'\n', false, true).0, 0);
+
+    assert_eq!(hash_list(&dir, b"\n\n", b'\n', false, false).0, 0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
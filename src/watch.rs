@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use crate::escape::display_name;
+use crate::hasher::{FileHash, HashError, STDIN_NAME};
+use crate::reffile::RefEntry;
+
+const EVENT_BUFFER_SIZE: usize = 4096;
+// Time without further events after which changed files are verified
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(500);
+
+// The directories containing the files are watched instead of the files themselves.
+// This way a file which is replaced by renaming another file to its name (as done by
+// most editors) is still noticed. MODIFY notices files which are written through a
+// descriptor that stays open, e.g. memory mapped files.
+fn watch_mask() -> WatchMask {
+    return WatchMask::CLOSE_WRITE | WatchMask::MODIFY | WatchMask::MOVED_TO | WatchMask::MOVED_FROM | WatchMask::DELETE;
+}
+
+// Re-verifies the files of reference data each time they are written, replaced or removed
+pub struct Watcher<'a> {
+    hasher: &'a mut dyn FileHash,
+    inotify: Inotify,
+    entries: HashMap<(WatchDescriptor, OsString), RefEntry>,
+    buffer: Vec<u8>
+}
+
+impl<'a> Watcher<'a> {
+    pub fn new(hasher: &'a mut dyn FileHash) -> io::Result<Watcher<'a>> {
+        return Ok(Watcher {
+            hasher,
+            inotify: Inotify::init()?,
+            entries: HashMap::new(),
+            buffer: vec![0; EVENT_BUFFER_SIZE]
+        });
+    }
+
+    pub fn add(&mut self, entry: RefEntry) -> Result<(), HashError> {
+        let file_name = match entry.file_name.file_name() {
+            Some(n) if entry.file_name != Path::new(STDIN_NAME) => n.to_os_string(),
+            _ => return Err(HashError::UnsupportedFileType(display_name(&entry.file_name)))
+        };
+
+        let dir = match entry.file_name.parent() {
+            Some(d) if d != Path::new("") => d.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+
+        let wd = match self.inotify.watches().add(&dir, watch_mask()) {
+            Ok(w) => w,
+            Err(e) => return Err(HashError::FileOpenError(display_name(&dir), e))
+        };
+
+        let key = (wd, file_name);
+        if self.entries.contains_key(&key) {
+            return Err(HashError::DuplicateEntry(display_name(&entry.file_name)));
+        }
+
+        self.entries.insert(key, entry);
+
+        return Ok(());
+    }
+
+    pub fn verify(&mut self, entry: &RefEntry) -> HashError {
+        return self.hasher.verify_file_region(&entry.file_name, &entry.region, &entry.hash);
+    }
+
+    // Adds the watched files of the pending events to changed. Returns false if there
+    // was no event for a watched file.
+    fn read_changes(&mut self, blocking: bool, changed: &mut Vec<(WatchDescriptor, OsString)>) -> io::Result<bool> {
+        let events = match blocking {
+            true => self.inotify.read_events_blocking(&mut self.buffer)?,
+            false => match self.inotify.read_events(&mut self.buffer) {
+                Ok(e) => e,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(e) => return Err(e)
+            }
+        };
+
+        let mut found = false;
+
+        for event in events {
+            if let Some(name) = event.name {
+                let key = (event.wd.clone(), name.to_os_string());
+
+                if self.entries.contains_key(&key) {
+                    found = true;
+
+                    if !changed.contains(&key) {
+                        changed.push(key);
+                    }
+                }
+            }
+        }
+
+        return Ok(found);
+    }
+
+    // Blocks until at least one of the watched files has changed and returns the
+    // result of verifying each changed file again. A file which is still being written
+    // is only verified once it has not changed for DEBOUNCE_INTERVAL.
+    pub fn wait(&mut self) -> io::Result<Vec<(PathBuf, HashError)>> {
+        let mut changed: Vec<(WatchDescriptor, OsString)> = Vec::new();
+
+        while !self.read_changes(true, &mut changed)? {}
+
+        loop {
+            thread::sleep(DEBOUNCE_INTERVAL);
+
+            if !self.read_changes(false, &mut changed)? {
+                break;
+            }
+        }
+
+        let mut results = Vec::new();
+
+        for key in changed {
+            let entry = &self.entries[&key];
+            let res = self.hasher.verify_file_region(&entry.file_name, &entry.region, &entry.hash);
+            results.push((entry.file_name.clone(), res));
+        }
+
+        return Ok(results);
+    }
+}
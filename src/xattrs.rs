@@ -0,0 +1,166 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use crate::escape::display_name;
+use crate::hasher::{DataHasher, FileHash, HashError, STDIN_NAME};
+use crate::merkle::MerkleTree;
+use crate::region::Region;
+
+// The hash of a file is stored in this extended attribute as
+//
+// <algorithm> <hex hash> <mtime>
+//
+// where mtime is the modification time of the file before it was hashed in the form
+// <seconds since the epoch>.<nanoseconds>
+pub const XATTR_NAME: &str = "user.rs256sum";
+
+#[cfg(unix)]
+fn mtime_string(meta: &fs::Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    return format!("{}.{:09}", meta.mtime(), meta.mtime_nsec());
+}
+
+#[cfg(not(unix))]
+fn mtime_string(meta: &fs::Metadata) -> String {
+    let since_epoch = meta.modified().ok().and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok()).unwrap_or_default();
+    return format!("{}.{:09}", since_epoch.as_secs(), since_epoch.subsec_nanos());
+}
+
+fn file_mtime(file_name: &Path) -> Result<String, HashError> {
+    return match fs::metadata(file_name) {
+        Ok(meta) => Ok(mtime_string(&meta)),
+        Err(e) => Err(HashError::FileOpenError(display_name(file_name), e))
+    };
+}
+
+pub struct StoredHash {
+    pub algo: String,
+    pub hash: String,
+    pub mtime: String
+}
+
+impl StoredHash {
+    pub fn parse(value: &str) -> Option<StoredHash> {
+        let parts: Vec<&str> = value.split(' ').collect();
+
+        if parts.len() != 3 {
+            return None;
+        }
+
+        return Some(StoredHash {
+            algo: parts[0].to_string(),
+            hash: parts[1].to_string(),
+            mtime: parts[2].to_string()
+        });
+    }
+
+    pub fn to_value(&self) -> String {
+        return format!("{} {} {}", self.algo, self.hash, self.mtime);
+    }
+}
+
+pub fn read_stored_hash(file_name: &Path) -> Result<StoredHash, HashError> {
+    let value = match xattr::get(file_name, XATTR_NAME) {
+        Ok(Some(v)) => v,
+        Ok(None) => return Err(HashError::StoredHashMissing(display_name(file_name))),
+        Err(e) => return Err(HashError::FileOpenError(display_name(file_name), e))
+    };
+
+    let value = String::from_utf8_lossy(&value).to_string();
+
+    return match StoredHash::parse(&value) {
+        Some(s) => Ok(s),
+        None => Err(HashError::StoredHashInvalid(display_name(file_name), value))
+    };
+}
+
+// Verifies a file against the hash stored in its extended attribute. hasher_for returns a
+// hasher for the stored algorithm if it is supported. In addition to the result it is
+// returned whether the file has been modified after it was hashed.
+pub fn verify_stored_hash(hasher_for: &mut dyn FnMut(&str) -> Option<Box<dyn FileHash>>, file_name: &Path) -> (HashError, bool) {
+    let stored = match read_stored_hash(file_name) {
+        Ok(s) => s,
+        Err(e) => return (e, false)
+    };
+
+    let mut hasher = match hasher_for(&stored.algo) {
+        Some(h) => h,
+        None => return (HashError::StoredHashInvalid(display_name(file_name), stored.to_value()), false)
+    };
+
+    let modified = match file_mtime(file_name) {
+        Ok(mtime) => mtime != stored.mtime,
+        Err(e) => return (e, false)
+    };
+
+    return (hasher.verify_file(file_name, &stored.hash), modified);
+}
+
+// Stores the hash of every file it hashes in the extended attribute of the file. Only
+// whole files can be hashed.
+pub struct XattrHasher {
+    inner: Box<dyn FileHash>
+}
+
+impl XattrHasher {
+    pub fn new(inner: Box<dyn FileHash>) -> XattrHasher {
+        return XattrHasher { inner };
+    }
+
+    fn store(&self, file_name: &Path, hash: &str, mtime: String) -> Result<(), HashError> {
+        let stored = StoredHash {
+            algo: self.inner.get_algo(),
+            hash: hash.to_string(),
+            mtime
+        };
+
+        return xattr::set(file_name, XATTR_NAME, stored.to_value().as_bytes())
+            .map_err(|e| HashError::FileOpenError(display_name(file_name), e));
+    }
+}
+
+impl DataHasher for XattrHasher {
+    fn hash_data(&mut self, r: &mut dyn Read) -> Result<String, HashError> {
+        return self.inner.hash_data(r);
+    }
+
+    fn hash_data_chunked(&mut self, r: &mut dyn Read, chunk_size: u64) -> Result<(String, Vec<String>), HashError> {
+        return self.inner.hash_data_chunked(r, chunk_size);
+    }
+
+    fn hash_data_tree(&mut self, r: &mut dyn Read, leaf_size: u64) -> Result<MerkleTree, HashError> {
+        return self.inner.hash_data_tree(r, leaf_size);
+    }
+}
+
+impl FileHash for XattrHasher {
+    fn get_algo(&self) -> String {
+        return self.inner.get_algo();
+    }
+
+    fn verify_data(&mut self, r: &mut dyn Read, hash: &str) -> HashError {
+        return self.inner.verify_data(r, hash);
+    }
+
+    fn hash_file_chunked(&mut self, file_name: &Path, _chunk_size: u64) -> Result<(String, Vec<String>), HashError> {
+        return Err(HashError::UnsupportedFileType(display_name(file_name)));
+    }
+
+    fn hash_file_region(&mut self, file_name: &Path, region: &Region) -> Result<String, HashError> {
+        if (file_name == Path::new(STDIN_NAME)) || !region.is_whole() {
+            return Err(HashError::UnsupportedFileType(display_name(file_name)));
+        }
+
+        // A modification while the file is hashed is noticed when it is verified
+        let mtime = file_mtime(file_name)?;
+        let hash = self.inner.hash_file(file_name)?;
+        self.store(file_name, &hash, mtime)?;
+
+        return Ok(hash);
+    }
+
+    // Verification does not touch the stored hash
+    fn verify_file_region(&mut self, file_name: &Path, region: &Region, hash: &str) -> HashError {
+        return self.inner.verify_file_region(file_name, region, hash);
+    }
+}